@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE grants;
+ALTER TABLE auth_challenges DROP COLUMN user_id;
+ALTER TABLE session DROP COLUMN user_id;
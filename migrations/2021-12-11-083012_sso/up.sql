@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE session ADD COLUMN user_id VARCHAR(255) NOT NULL;
+ALTER TABLE auth_challenges ADD COLUMN user_id VARCHAR(255);
+CREATE TABLE grants (
+  user_id VARCHAR(255) NOT NULL,
+  client_id VARCHAR(255) NOT NULL,
+  scope VARCHAR(255) NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+  PRIMARY KEY (user_id, client_id)
+);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE auth_code DROP COLUMN max_age;
+ALTER TABLE auth_code DROP COLUMN auth_time;
+ALTER TABLE auth_challenges DROP COLUMN max_age;
+ALTER TABLE auth_challenges DROP COLUMN auth_time;
+ALTER TABLE client DROP COLUMN require_auth_time;
+ALTER TABLE session DROP COLUMN auth_time;
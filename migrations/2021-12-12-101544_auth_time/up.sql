@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE session ADD COLUMN auth_time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE client ADD COLUMN require_auth_time BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE auth_challenges ADD COLUMN auth_time DATETIME;
+ALTER TABLE auth_challenges ADD COLUMN max_age BIGINT UNSIGNED;
+ALTER TABLE auth_code ADD COLUMN auth_time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE auth_code ADD COLUMN max_age BIGINT UNSIGNED;
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE auth_challenges ADD COLUMN response_mode VARCHAR(255) NOT NULL DEFAULT 'query';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE client DROP COLUMN authorization_encrypted_response_enc;
+ALTER TABLE client DROP COLUMN authorization_encrypted_response_alg;
+ALTER TABLE client DROP COLUMN jwks;
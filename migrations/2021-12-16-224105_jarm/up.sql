@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE client ADD COLUMN jwks TEXT;
+ALTER TABLE client ADD COLUMN authorization_encrypted_response_alg VARCHAR(255);
+ALTER TABLE client ADD COLUMN authorization_encrypted_response_enc VARCHAR(255);
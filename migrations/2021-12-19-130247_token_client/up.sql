@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE tokens ADD COLUMN client_id VARCHAR(255) NOT NULL DEFAULT '';
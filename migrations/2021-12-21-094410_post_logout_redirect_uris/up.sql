@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE client ADD COLUMN post_logout_redirect_uris TEXT;
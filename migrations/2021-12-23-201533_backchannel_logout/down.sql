@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE logout_deliveries;
+DROP TABLE session_clients;
+ALTER TABLE client DROP COLUMN backchannel_logout_session_required;
+ALTER TABLE client DROP COLUMN backchannel_logout_uri;
+ALTER TABLE auth_code DROP COLUMN sid;
+ALTER TABLE auth_challenges DROP COLUMN sid;
+ALTER TABLE session DROP COLUMN sid;
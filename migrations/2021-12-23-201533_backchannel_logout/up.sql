@@ -0,0 +1,22 @@
+-- Your SQL goes here
+ALTER TABLE session ADD COLUMN sid VARCHAR(255) NOT NULL;
+ALTER TABLE auth_challenges ADD COLUMN sid VARCHAR(255);
+ALTER TABLE auth_code ADD COLUMN sid VARCHAR(255);
+ALTER TABLE client ADD COLUMN backchannel_logout_uri VARCHAR(255);
+ALTER TABLE client ADD COLUMN backchannel_logout_session_required BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE TABLE session_clients (
+  sid VARCHAR(255) NOT NULL,
+  client_id VARCHAR(255) NOT NULL,
+  PRIMARY KEY (sid, client_id)
+);
+CREATE TABLE logout_deliveries (
+  id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  sid VARCHAR(255) NOT NULL,
+  client_id VARCHAR(255) NOT NULL,
+  uri VARCHAR(255) NOT NULL,
+  attempt INTEGER NOT NULL,
+  status INTEGER,
+  error TEXT,
+  delivered BOOLEAN NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE client DROP COLUMN frontchannel_logout_session_required;
+ALTER TABLE client DROP COLUMN frontchannel_logout_uri;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE client ADD COLUMN frontchannel_logout_uri VARCHAR(255);
+ALTER TABLE client ADD COLUMN frontchannel_logout_session_required BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE session DROP COLUMN last_seen_at;
+ALTER TABLE session DROP COLUMN expires_at;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE session ADD COLUMN expires_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE session ADD COLUMN last_seen_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE auth_challenges DROP COLUMN consumed;
+ALTER TABLE auth_challenges DROP COLUMN created_at;
+ALTER TABLE auth_challenges DROP COLUMN csrf_token;
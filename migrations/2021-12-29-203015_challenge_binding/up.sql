@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE auth_challenges ADD COLUMN csrf_token VARCHAR(255) NOT NULL DEFAULT '';
+ALTER TABLE auth_challenges ADD COLUMN created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE auth_challenges ADD COLUMN consumed BOOLEAN NOT NULL DEFAULT FALSE;
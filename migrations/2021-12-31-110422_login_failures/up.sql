@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE login_failures (
+  scope VARCHAR(16) NOT NULL,
+  subject VARCHAR(255) NOT NULL,
+  failures INTEGER NOT NULL DEFAULT 0,
+  last_failed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  locked_until DATETIME,
+  PRIMARY KEY (scope, subject)
+);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE auth_code DROP COLUMN acr;
+ALTER TABLE auth_code DROP COLUMN amr;
+ALTER TABLE auth_challenges DROP COLUMN acr;
+ALTER TABLE auth_challenges DROP COLUMN amr;
+ALTER TABLE session DROP COLUMN acr;
+ALTER TABLE session DROP COLUMN amr;
+DROP TABLE recovery_codes;
+DROP TABLE totp_credentials;
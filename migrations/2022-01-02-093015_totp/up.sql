@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE totp_credentials (
+  user_id VARCHAR(255) PRIMARY KEY,
+  secret VARCHAR(255) NOT NULL,
+  confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+  last_used_step BIGINT,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE recovery_codes (
+  user_id VARCHAR(255) NOT NULL,
+  code_hash VARCHAR(255) NOT NULL,
+  PRIMARY KEY (user_id, code_hash)
+);
+ALTER TABLE session ADD COLUMN amr VARCHAR(255) NOT NULL DEFAULT 'pwd';
+ALTER TABLE session ADD COLUMN acr VARCHAR(255) NOT NULL DEFAULT 'urn:oidc-rs:acr:1fa';
+ALTER TABLE auth_challenges ADD COLUMN amr VARCHAR(255);
+ALTER TABLE auth_challenges ADD COLUMN acr VARCHAR(255);
+ALTER TABLE auth_code ADD COLUMN amr VARCHAR(255);
+ALTER TABLE auth_code ADD COLUMN acr VARCHAR(255);
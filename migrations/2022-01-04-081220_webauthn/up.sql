@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE webauthn_credentials (
+  credential_id VARCHAR(255) PRIMARY KEY,
+  user_id VARCHAR(255) NOT NULL,
+  public_key TEXT NOT NULL,
+  sign_count BIGINT NOT NULL DEFAULT 0,
+  backup_eligible BOOLEAN NOT NULL DEFAULT FALSE,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  last_used_at DATETIME,
+  INDEX (user_id)
+);
+CREATE TABLE webauthn_challenges (
+  challenge VARCHAR(255) PRIMARY KEY,
+  user_id VARCHAR(255),
+  login_challenge VARCHAR(255),
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
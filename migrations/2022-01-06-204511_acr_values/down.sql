@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE auth_challenges DROP COLUMN acr_essential;
+ALTER TABLE auth_challenges DROP COLUMN acr_values;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE auth_challenges ADD COLUMN acr_values VARCHAR(1024);
+ALTER TABLE auth_challenges ADD COLUMN acr_essential BOOLEAN NOT NULL DEFAULT FALSE;
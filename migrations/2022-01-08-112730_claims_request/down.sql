@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE tokens DROP COLUMN claims;
+ALTER TABLE auth_code DROP COLUMN claims;
+ALTER TABLE auth_challenges DROP COLUMN claims;
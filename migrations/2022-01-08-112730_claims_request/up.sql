@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE auth_challenges ADD COLUMN claims TEXT;
+ALTER TABLE auth_code ADD COLUMN claims TEXT;
+ALTER TABLE tokens ADD COLUMN claims TEXT;
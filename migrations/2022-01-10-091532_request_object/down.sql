@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE client DROP COLUMN request_uris;
+ALTER TABLE client DROP COLUMN require_signed_request_object;
+ALTER TABLE client DROP COLUMN request_object_signing_alg;
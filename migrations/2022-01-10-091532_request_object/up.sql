@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE client ADD COLUMN request_object_signing_alg VARCHAR(255);
+ALTER TABLE client ADD COLUMN require_signed_request_object BOOL NOT NULL DEFAULT FALSE;
+ALTER TABLE client ADD COLUMN request_uris TEXT;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE client DROP COLUMN require_pushed_authorization_requests;
+DROP TABLE pushed_authorization_requests;
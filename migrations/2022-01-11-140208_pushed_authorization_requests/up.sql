@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE pushed_authorization_requests (
+  request_uri VARCHAR(255) PRIMARY KEY,
+  client_id VARCHAR(255) NOT NULL,
+  params TEXT NOT NULL,
+  expires_at DATETIME NOT NULL
+);
+ALTER TABLE client ADD COLUMN require_pushed_authorization_requests BOOL NOT NULL DEFAULT FALSE;
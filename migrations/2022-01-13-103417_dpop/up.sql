@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE tokens ADD COLUMN dpop_jkt VARCHAR(255);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE tokens DROP COLUMN x5t_s256;
+ALTER TABLE client DROP COLUMN tls_client_certificate_bound_access_tokens;
+ALTER TABLE client DROP COLUMN tls_client_auth_san_dns;
+ALTER TABLE client DROP COLUMN tls_client_auth_subject_dn;
+ALTER TABLE client DROP COLUMN token_endpoint_auth_method;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE client ADD COLUMN token_endpoint_auth_method VARCHAR(255) NOT NULL DEFAULT 'client_secret_basic';
+ALTER TABLE client ADD COLUMN tls_client_auth_subject_dn VARCHAR(1024);
+ALTER TABLE client ADD COLUMN tls_client_auth_san_dns VARCHAR(255);
+ALTER TABLE client ADD COLUMN tls_client_certificate_bound_access_tokens BOOL NOT NULL DEFAULT FALSE;
+ALTER TABLE tokens ADD COLUMN x5t_s256 VARCHAR(255);
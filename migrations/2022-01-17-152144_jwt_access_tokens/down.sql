@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE client DROP COLUMN access_token_audience;
+ALTER TABLE client DROP COLUMN access_token_jwt;
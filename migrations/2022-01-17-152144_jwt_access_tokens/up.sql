@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE client ADD COLUMN access_token_jwt BOOL NOT NULL DEFAULT FALSE;
+ALTER TABLE client ADD COLUMN access_token_audience VARCHAR(255);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE session ADD COLUMN csrf_token VARCHAR(64) NOT NULL DEFAULT '';
@@ -0,0 +1,146 @@
+use serde::Deserialize;
+
+use crate::mfa::{AMR_HARDWARE_KEY, AMR_OTP, AMR_PASSWORD, AMR_SOFTWARE_KEY};
+
+/// Authentication context class references this provider asserts in `acr`
+pub const ACR_SINGLE_FACTOR: &str = "urn:oidc-rs:acr:1fa";
+pub const ACR_MULTI_FACTOR: &str = "urn:oidc-rs:acr:2fa";
+
+/// An `acr` value and the authentication methods that satisfy it
+#[derive(Deserialize, Clone, Debug)]
+pub struct AcrLevel {
+    pub acr: String,
+    /// alternatives, each listing `amr` values that all have to be used
+    pub methods: Vec<Vec<String>>,
+}
+
+impl AcrLevel {
+    fn new(acr: &str, methods: &[&[&str]]) -> Self {
+        AcrLevel {
+            acr: acr.to_string(),
+            methods: methods
+                .iter()
+                .map(|m| m.iter().map(|a| a.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    fn is_satisfied_by(&self, amr: &[&str]) -> bool {
+        self.methods
+            .iter()
+            .any(|methods| methods.iter().all(|m| amr.contains(&m.as_str())))
+    }
+}
+
+/// Which authentication methods satisfy which `acr` value, read from Rocket's
+/// configuration (`acr_levels`). Levels are listed from the weakest to the strongest.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AcrPolicy {
+    #[serde(default = "default_acr_levels")]
+    pub acr_levels: Vec<AcrLevel>,
+}
+
+/// Passkey logins always verify the user, so the key is a second factor on its own
+fn default_acr_levels() -> Vec<AcrLevel> {
+    vec![
+        AcrLevel::new(
+            ACR_SINGLE_FACTOR,
+            &[&[AMR_PASSWORD], &[AMR_HARDWARE_KEY], &[AMR_SOFTWARE_KEY]],
+        ),
+        AcrLevel::new(
+            ACR_MULTI_FACTOR,
+            &[
+                &[AMR_PASSWORD, AMR_OTP],
+                &[AMR_HARDWARE_KEY],
+                &[AMR_SOFTWARE_KEY],
+            ],
+        ),
+    ]
+}
+
+impl Default for AcrPolicy {
+    fn default() -> Self {
+        AcrPolicy {
+            acr_levels: default_acr_levels(),
+        }
+    }
+}
+
+impl AcrPolicy {
+    /// The `acr` values for `acr_values_supported`
+    pub fn supported(&self) -> Vec<String> {
+        self.acr_levels.iter().map(|l| l.acr.clone()).collect()
+    }
+
+    /// The requested levels this provider knows, in the order of preference
+    fn known<'a>(&'a self, requested: &[&str]) -> Vec<&'a AcrLevel> {
+        requested
+            .iter()
+            .filter_map(|r| self.acr_levels.iter().find(|l| l.acr == *r))
+            .collect()
+    }
+
+    /// Whether any of the requested `acr` values is one this provider can satisfy
+    pub fn is_supported(&self, requested: &[&str]) -> bool {
+        !self.known(requested).is_empty()
+    }
+
+    /// Whether a login with these methods satisfies the request.
+    /// Values this provider doesn't know are ignored.
+    pub fn satisfies(&self, requested: &[&str], amr: &[&str]) -> bool {
+        let known = self.known(requested);
+        known.is_empty() || known.iter().any(|l| l.is_satisfied_by(amr))
+    }
+
+    /// The `acr` to assert for a login with these methods: the first requested
+    /// value it satisfies, otherwise the strongest level it reaches
+    pub fn acr(&self, requested: &[&str], amr: &[&str]) -> Option<&str> {
+        self.known(requested)
+            .into_iter()
+            .chain(self.acr_levels.iter().rev())
+            .find(|l| l.is_satisfied_by(amr))
+            .map(|l| l.acr.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acr_ok() {
+        let policy = AcrPolicy::default();
+        assert_eq!(Some(ACR_SINGLE_FACTOR), policy.acr(&[], &[AMR_PASSWORD]));
+        assert_eq!(
+            Some(ACR_MULTI_FACTOR),
+            policy.acr(&[], &[AMR_PASSWORD, AMR_OTP])
+        );
+        assert_eq!(Some(ACR_MULTI_FACTOR), policy.acr(&[], &[AMR_HARDWARE_KEY]));
+        // the requested value wins over the strongest one
+        assert_eq!(
+            Some(ACR_SINGLE_FACTOR),
+            policy.acr(&["unknown", ACR_SINGLE_FACTOR], &[AMR_PASSWORD, AMR_OTP])
+        );
+        assert_eq!(None, policy.acr(&[], &[AMR_OTP]));
+    }
+
+    #[test]
+    fn satisfies_ok() {
+        let policy = AcrPolicy::default();
+        assert!(policy.satisfies(&[], &[AMR_PASSWORD]));
+        assert!(policy.satisfies(&["unknown"], &[AMR_PASSWORD]));
+        assert!(policy.satisfies(&[ACR_SINGLE_FACTOR], &[AMR_PASSWORD]));
+        assert!(policy.satisfies(&[ACR_MULTI_FACTOR], &[AMR_SOFTWARE_KEY]));
+        assert!(policy.satisfies(&[ACR_MULTI_FACTOR, ACR_SINGLE_FACTOR], &[AMR_PASSWORD]));
+        assert!(!policy.satisfies(&[ACR_MULTI_FACTOR], &[AMR_PASSWORD]));
+        assert!(!policy.satisfies(&[ACR_MULTI_FACTOR, "unknown"], &[AMR_OTP]));
+    }
+
+    #[test]
+    fn is_supported_ok() {
+        let policy = AcrPolicy::default();
+        assert!(policy.is_supported(&["unknown", ACR_MULTI_FACTOR]));
+        assert!(!policy.is_supported(&["unknown"]));
+        assert!(!policy.is_supported(&[]));
+    }
+}
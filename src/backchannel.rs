@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{message::discovery::ISSUER, models::NewLogoutDelivery, utils::generate_challenge};
+
+pub const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+const MAX_ATTEMPTS: i32 = 3;
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// seconds a logout token is valid for; covers the retries of every relying party
+const LOGOUT_TOKEN_LIFETIME: i64 = 2 * 60;
+
+/// LogoutToken represents the claims of a logout token
+/// https://openid.net/specs/openid-connect-backchannel-1_0.html#LogoutToken
+#[derive(Serialize)]
+pub struct LogoutToken {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub iat: usize,
+    pub exp: usize,
+    pub jti: String,
+    pub sid: String,
+    pub events: Value,
+}
+
+impl LogoutToken {
+    pub fn new(user_id: &str, client_id: &str, sid: &str) -> Self {
+        let now = Utc::now().timestamp();
+        Self {
+            iss: ISSUER.to_string(),
+            sub: user_id.to_string(),
+            aud: client_id.to_string(),
+            iat: now as usize,
+            exp: (now + LOGOUT_TOKEN_LIFETIME) as usize,
+            jti: generate_challenge(),
+            sid: sid.to_string(),
+            events: json!({ BACKCHANNEL_LOGOUT_EVENT: {} }),
+        }
+    }
+}
+
+/// LogoutTarget is a relying party to be notified of the end of a session
+pub struct LogoutTarget {
+    pub sid: String,
+    pub client_id: String,
+    pub uri: String,
+    pub logout_token: String,
+}
+
+/// Posts the logout token to the relying party, retrying with exponential back-off.
+/// Returns one delivery record per attempt.
+pub async fn deliver(
+    http: &reqwest::Client,
+    target: &LogoutTarget,
+    backoff: Duration,
+) -> Vec<NewLogoutDelivery> {
+    let mut deliveries = vec![];
+    let mut wait = backoff;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let res = http
+            .post(&target.uri)
+            .timeout(TIMEOUT)
+            .form(&[("logout_token", &target.logout_token)])
+            .send()
+            .await;
+        let (status, error, delivered) = match res {
+            Ok(r) => (
+                Some(r.status().as_u16() as i32),
+                None,
+                r.status().is_success(),
+            ),
+            Err(e) => (None, Some(e.to_string()), false),
+        };
+        deliveries.push(NewLogoutDelivery {
+            sid: target.sid.clone(),
+            client_id: target.client_id.clone(),
+            uri: target.uri.clone(),
+            attempt,
+            status,
+            error,
+            delivered,
+        });
+        if delivered || attempt == MAX_ATTEMPTS {
+            break;
+        }
+        rocket::tokio::time::sleep(wait).await;
+        wait *= 2;
+    }
+    deliveries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+    };
+
+    use super::*;
+
+    /// Serves `count` requests with the given status line and hands the requests back
+    fn stub(status: &'static str, count: usize) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!(
+            "http://{}/backchannel_logout",
+            listener.local_addr().unwrap()
+        );
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for _ in 0..count {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 8192];
+                let n = stream.read(&mut buf).unwrap();
+                tx.send(String::from_utf8_lossy(&buf[..n]).to_string())
+                    .unwrap();
+                let res = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+                stream.write_all(res.as_bytes()).unwrap();
+            }
+        });
+        (uri, rx)
+    }
+
+    fn target(uri: String) -> LogoutTarget {
+        LogoutTarget {
+            sid: String::from("sid"),
+            client_id: String::from("client"),
+            uri,
+            logout_token: String::from("a.b.c"),
+        }
+    }
+
+    #[rocket::async_test]
+    async fn deliver_ok() {
+        let (uri, rx) = stub("200 OK", 1);
+        let deliveries = deliver(&reqwest::Client::new(), &target(uri), Duration::ZERO).await;
+        assert_eq!(1, deliveries.len());
+        assert!(deliveries[0].delivered);
+        assert_eq!(Some(200), deliveries[0].status);
+        assert!(rx.recv().unwrap().starts_with("POST /backchannel_logout"));
+    }
+
+    #[rocket::async_test]
+    async fn deliver_retries() {
+        let (uri, _rx) = stub("500 Internal Server Error", 3);
+        let deliveries = deliver(&reqwest::Client::new(), &target(uri), Duration::ZERO).await;
+        assert_eq!(3, deliveries.len());
+        assert!(deliveries.iter().all(|d| !d.delivered));
+        assert_eq!(3, deliveries[2].attempt);
+    }
+
+    #[test]
+    fn logout_token_has_event() {
+        let token = LogoutToken::new("userid", "client", "sid");
+        assert!(token.events.get(BACKCHANNEL_LOGOUT_EVENT).is_some());
+        assert_eq!("sid", token.sid);
+        assert_eq!(token.iat + 120, token.exp);
+    }
+}
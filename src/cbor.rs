@@ -0,0 +1,223 @@
+//! Minimal CBOR (RFC 8949) decoder for WebAuthn attestation objects and COSE keys.
+//! Only definite-length items are supported; floats and tags are rejected.
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CborError {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("unsupported item")]
+    Unsupported,
+    #[error("nested too deeply")]
+    TooDeep,
+    #[error("invalid UTF-8 in text string")]
+    InvalidText,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// Looks up a map entry by integer key, as COSE keys use
+    pub fn get_int(&self, key: i128) -> Option<&Value> {
+        self.get(&Value::Integer(key))
+    }
+
+    /// Looks up a map entry by text key
+    pub fn get_text(&self, key: &str) -> Option<&Value> {
+        self.get(&Value::Text(key.to_string()))
+    }
+
+    fn get(&self, key: &Value) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+const MAX_DEPTH: usize = 16;
+
+/// Decodes the first item in `data`. Returns it together with the number of
+/// bytes it took, since WebAuthn appends a COSE key to other binary data.
+pub fn decode(data: &[u8]) -> Result<(Value, usize), CborError> {
+    let mut decoder = Decoder { data, pos: 0 };
+    let value = decoder.item(0)?;
+    Ok((value, decoder.pos))
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CborError> {
+        let end = self.pos.checked_add(len).ok_or(CborError::UnexpectedEnd)?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(CborError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Reads the argument that follows the initial byte
+    fn argument(&mut self, info: u8) -> Result<u64, CborError> {
+        let len = match info {
+            0..=23 => return Ok(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(CborError::Unsupported),
+        };
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn length(&mut self, info: u8) -> Result<usize, CborError> {
+        let len = self.argument(info)? as usize;
+        // every item takes at least one byte, which bounds what a length can claim
+        if len > self.data.len() - self.pos {
+            return Err(CborError::UnexpectedEnd);
+        }
+        Ok(len)
+    }
+
+    fn item(&mut self, depth: usize) -> Result<Value, CborError> {
+        if depth > MAX_DEPTH {
+            return Err(CborError::TooDeep);
+        }
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        match major {
+            0 => Ok(Value::Integer(self.argument(info)? as i128)),
+            1 => Ok(Value::Integer(-1 - self.argument(info)? as i128)),
+            2 => {
+                let len = self.length(info)?;
+                Ok(Value::Bytes(self.take(len)?.to_vec()))
+            }
+            3 => {
+                let len = self.length(info)?;
+                let text = std::str::from_utf8(self.take(len)?).or(Err(CborError::InvalidText))?;
+                Ok(Value::Text(text.to_string()))
+            }
+            4 => {
+                let len = self.length(info)?;
+                let items = (0..len)
+                    .map(|_| self.item(depth + 1))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Array(items))
+            }
+            5 => {
+                let len = self.length(info)?;
+                let entries = (0..len)
+                    .map(|_| Ok((self.item(depth + 1)?, self.item(depth + 1)?)))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Map(entries))
+            }
+            7 => match info {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 | 23 => Ok(Value::Null),
+                _ => Err(CborError::Unsupported),
+            },
+            _ => Err(CborError::Unsupported),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_ok() {
+        // {"fmt": "none", 1: -7, "b": h'0102', "a": [true, null]}
+        let data = [
+            0xa4, 0x63, b'f', b'm', b't', 0x64, b'n', b'o', b'n', b'e', 0x01, 0x26, 0x61, b'b',
+            0x42, 0x01, 0x02, 0x61, b'a', 0x82, 0xf5, 0xf6,
+        ];
+        let (value, len) = decode(&data).unwrap();
+        assert_eq!(data.len(), len);
+        assert_eq!(
+            Some("none"),
+            value.get_text("fmt").and_then(|v| v.as_text())
+        );
+        assert_eq!(Some(-7), value.get_int(1).and_then(|v| v.as_integer()));
+        assert_eq!(
+            Some(&[1u8, 2][..]),
+            value.get_text("b").and_then(|v| v.as_bytes())
+        );
+        assert_eq!(
+            Some(&Value::Array(vec![Value::Bool(true), Value::Null])),
+            value.get_text("a")
+        );
+    }
+
+    #[test]
+    fn decode_reports_trailing_data() {
+        let (value, len) = decode(&[0x19, 0x01, 0x00, 0xff]).unwrap();
+        assert_eq!(Value::Integer(256), value);
+        assert_eq!(3, len);
+    }
+
+    #[test]
+    fn decode_ng() {
+        // truncated byte string
+        assert_eq!(
+            Err(CborError::UnexpectedEnd),
+            decode(&[0x43, 0x01]).map(|_| ())
+        );
+        // a length larger than the input
+        assert_eq!(
+            Err(CborError::UnexpectedEnd),
+            decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).map(|_| ())
+        );
+        // indefinite-length array
+        assert_eq!(
+            Err(CborError::Unsupported),
+            decode(&[0x9f, 0xff]).map(|_| ())
+        );
+        // float
+        assert_eq!(
+            Err(CborError::Unsupported),
+            decode(&[0xf9, 0x3c, 0x00]).map(|_| ())
+        );
+        // deeply nested arrays
+        assert_eq!(Err(CborError::TooDeep), decode(&[0x81; 32]).map(|_| ()));
+    }
+}
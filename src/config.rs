@@ -0,0 +1,88 @@
+use serde::Deserialize;
+
+/// Session and login flow settings read from Rocket's configuration (`Rocket.toml` or `ROCKET_*` variables)
+#[derive(Deserialize, Clone, Copy)]
+pub struct SessionConfig {
+    /// seconds a session lasts after login regardless of activity
+    #[serde(default = "default_absolute_lifetime")]
+    pub session_absolute_lifetime: i64,
+    /// seconds a session survives without being used
+    #[serde(default = "default_idle_timeout")]
+    pub session_idle_timeout: i64,
+    /// only send the session cookies over HTTPS
+    #[serde(default = "default_cookie_secure")]
+    pub session_cookie_secure: bool,
+    /// seconds the end-user has to finish login and consent for an authentication request
+    #[serde(default = "default_challenge_lifetime")]
+    pub challenge_lifetime: i64,
+    /// consecutive failed logins for one account before it is locked out
+    #[serde(default = "default_lockout_account_threshold")]
+    pub lockout_account_threshold: i32,
+    /// consecutive failed logins from one IP address before it is locked out
+    #[serde(default = "default_lockout_ip_threshold")]
+    pub lockout_ip_threshold: i32,
+    /// seconds a lockout lasts; also caps the back-off between failed attempts
+    #[serde(default = "default_lockout_duration")]
+    pub lockout_duration: i64,
+    /// seconds to wait after the first failed login, doubled after each further failure
+    #[serde(default = "default_login_backoff_base")]
+    pub login_backoff_base: i64,
+}
+
+fn default_absolute_lifetime() -> i64 {
+    12 * 60 * 60
+}
+
+fn default_idle_timeout() -> i64 {
+    30 * 60
+}
+
+fn default_cookie_secure() -> bool {
+    true
+}
+
+fn default_challenge_lifetime() -> i64 {
+    10 * 60
+}
+
+fn default_lockout_account_threshold() -> i32 {
+    5
+}
+
+fn default_lockout_ip_threshold() -> i32 {
+    20
+}
+
+fn default_lockout_duration() -> i64 {
+    15 * 60
+}
+
+fn default_login_backoff_base() -> i64 {
+    1
+}
+
+/// Where the end-user's IP address, which failed logins are counted against, comes from.
+/// It is the address of the connection's peer unless `client_ip_header` names the header
+/// a reverse proxy in front of every request puts the client's address in. The proxy must
+/// overwrite or append to what clients send in that header, which can't be trusted otherwise.
+#[derive(Deserialize, Clone)]
+pub struct ProxyConfig {
+    #[serde(default)]
+    pub client_ip_header: Option<String>,
+}
+
+/// Where client certificates for mutual TLS come from. Rocket 0.5.0-rc.1 can't ask
+/// for or read a client certificate in its own TLS listener, so mutual TLS only works
+/// behind a reverse proxy that terminates TLS and forwards the certificate in a header
+/// (URL-encoded PEM, as nginx's `$ssl_client_escaped_cert`). Without
+/// `mtls_client_cert_header`, no certificate is trusted.
+/// The provider doesn't check certificate chains itself: `tls_client_auth` clients
+/// are only authenticated when the proxy says it verified the chain by sending
+/// `SUCCESS` in `mtls_client_verify_header` (as nginx's `$ssl_client_verify`).
+#[derive(Deserialize, Clone)]
+pub struct MtlsConfig {
+    #[serde(default)]
+    pub mtls_client_cert_header: Option<String>,
+    #[serde(default)]
+    pub mtls_client_verify_header: Option<String>,
+}
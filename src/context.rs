@@ -1,15 +1,42 @@
 use serde::Serialize;
 
+use crate::message::webauthn::CreationOptions;
+
 #[derive(Serialize)]
 pub struct LoginContext {
     pub error_msg: Option<String>,
     pub login_challenge: String,
+    pub csrf_token: String,
+    pub state: Option<String>,
+    /// set while too many failed attempts block logging in
+    pub locked_until: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct MfaContext {
+    pub error_msg: Option<String>,
+    pub login_challenge: String,
+    pub csrf_token: String,
     pub state: Option<String>,
+    pub locked_until: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ScopeContext {
+    pub name: String,
+    pub description: String,
+    pub claims: Vec<String>,
+    pub required: bool,
 }
 
 #[derive(Serialize)]
 pub struct ConsentContext {
+    pub client_id: String,
+    pub scopes: Vec<ScopeContext>,
+    /// claims requested through the `claims` parameter that no requested scope covers
+    pub claims: Vec<String>,
     pub consent_challenge: String,
+    pub csrf_token: String,
     pub state: Option<String>,
 }
 
@@ -17,3 +44,85 @@ pub struct ConsentContext {
 pub struct ErrorContext {
     pub error_msg: String,
 }
+
+#[derive(Serialize)]
+pub struct FormPostParam {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize)]
+pub struct FormPostContext {
+    pub redirect_uri: String,
+    pub params: Vec<FormPostParam>,
+}
+
+#[derive(Serialize)]
+pub struct SessionContext {
+    pub auth_time: String,
+    pub current: bool,
+}
+
+#[derive(Serialize)]
+pub struct GrantContext {
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub granted_at: String,
+}
+
+#[derive(Serialize)]
+pub struct AccountContext {
+    pub sessions: Vec<SessionContext>,
+    pub grants: Vec<GrantContext>,
+    pub mfa_enabled: bool,
+    pub csrf_token: String,
+}
+
+#[derive(Serialize)]
+pub struct TotpContext {
+    pub enabled: bool,
+    /// the secret and its provisioning URI are only shown until enrollment is confirmed
+    pub secret: Option<String>,
+    pub provisioning_uri: Option<String>,
+    pub recovery_codes_left: i64,
+    pub error_msg: Option<String>,
+    pub csrf_token: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesContext {
+    pub codes: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct WebAuthnCredentialContext {
+    pub credential_id: String,
+    /// whether the passkey may be synced between devices
+    pub synced: bool,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WebAuthnContext {
+    pub credentials: Vec<WebAuthnCredentialContext>,
+    /// handed to `navigator.credentials.create()` to register another passkey
+    pub creation_options: CreationOptions,
+    pub error_msg: Option<String>,
+    pub csrf_token: String,
+}
+
+#[derive(Serialize)]
+pub struct LogoutContext {
+    pub id_token_hint: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    pub client_id: Option<String>,
+    pub state: Option<String>,
+    pub csrf_token: String,
+}
+
+#[derive(Serialize)]
+pub struct LoggedOutContext {
+    pub frontchannel_logout_uris: Vec<String>,
+    pub next: Option<String>,
+}
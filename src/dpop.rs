@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Cursor,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use josekit::{
+    jwk::Jwk,
+    jwt::{self, JwtPayload},
+};
+use rocket::{
+    http::{ContentType, Header, Status},
+    request::{self, FromRequest, Outcome},
+    response::Responder,
+    Request, Response,
+};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    message::discovery::ISSUER,
+    utils::{generate_challenge, jws_verifier, sha256_base64url, JWS_ALGS},
+};
+
+/// Proofs issued further from now than this, in seconds, are rejected
+const PROOF_LIFETIME: u64 = 300;
+/// Nonces are replaced this often, in seconds; the previous one stays valid
+/// so that a client racing the rotation doesn't fail
+const NONCE_LIFETIME: u64 = 300;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("something went wrong when calculate timestamp")
+        .as_secs()
+}
+
+/// DpopProof is the `DPoP` header of a request, with the method and URI the
+/// proof has to be for
+/// https://www.rfc-editor.org/rfc/rfc9449.html#section-4
+pub struct DpopProof {
+    pub proof: Option<String>,
+    htm: String,
+    htu: String,
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for DpopProof {
+    type Error = anyhow::Error;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let mut proofs = request.headers().get("DPoP");
+        let proof = proofs.next().map(String::from);
+        if proofs.next().is_some() {
+            return Outcome::Failure((Status::BadRequest, anyhow::anyhow!("multiple proofs")));
+        }
+        Outcome::Success(DpopProof {
+            proof,
+            htm: request.method().as_str().to_string(),
+            htu: format!("{}{}", ISSUER, request.uri().path()),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DpopErrorKind {
+    InvalidDpopProof,
+    UseDpopNonce,
+}
+
+impl fmt::Display for DpopErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DpopErrorKind::InvalidDpopProof => write!(f, "invalid_dpop_proof"),
+            DpopErrorKind::UseDpopNonce => write!(f, "use_dpop_nonce"),
+        }
+    }
+}
+
+/// DpopError carries the nonce the client has to use in its next proof.
+/// The token endpoint returns it as an OAuth error, protected resources in
+/// `WWW-Authenticate`.
+/// https://www.rfc-editor.org/rfc/rfc9449.html#section-8
+#[derive(Debug, Error)]
+#[error("{kind}")]
+pub struct DpopError {
+    pub kind: DpopErrorKind,
+    nonce: String,
+    resource: bool,
+}
+
+impl DpopError {
+    fn new(kind: DpopErrorKind, nonce: String) -> Self {
+        Self {
+            kind,
+            nonce,
+            resource: false,
+        }
+    }
+
+    /// Answers as a protected resource rather than as the token endpoint
+    pub fn at_resource(mut self) -> Self {
+        self.resource = true;
+        self
+    }
+}
+
+impl<'r> Responder<'r, 'static> for DpopError {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let mut res = Response::build();
+        res.header(Header::new("DPoP-Nonce", self.nonce));
+        if self.resource {
+            res.status(Status::Unauthorized).header(Header::new(
+                "WWW-Authenticate",
+                format!(
+                    "DPoP error=\"{}\", algs=\"{}\"",
+                    self.kind,
+                    JWS_ALGS.join(" ")
+                ),
+            ));
+        } else {
+            let body = json!({ "error": self.kind.to_string() }).to_string();
+            res.status(Status::BadRequest)
+                .header(ContentType::JSON)
+                .sized_body(body.len(), Cursor::new(body));
+        }
+        res.ok()
+    }
+}
+
+struct Nonces {
+    current: String,
+    previous: String,
+    rotated_at: u64,
+}
+
+/// DpopState holds the nonces handed out to clients and the `jti` of the
+/// proofs seen while they are fresh, so that a proof can't be replayed.
+/// Both are kept in memory, which is enough for a single instance.
+pub struct DpopState {
+    nonces: Mutex<Nonces>,
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl Default for DpopState {
+    fn default() -> Self {
+        Self {
+            nonces: Mutex::new(Nonces {
+                current: generate_challenge(),
+                previous: generate_challenge(),
+                rotated_at: now(),
+            }),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl DpopState {
+    /// The nonce clients have to put in their proofs
+    pub fn nonce(&self) -> String {
+        let mut nonces = self.nonces.lock().expect("nonce lock poisoned");
+        if now() >= nonces.rotated_at + NONCE_LIFETIME {
+            nonces.previous = std::mem::replace(&mut nonces.current, generate_challenge());
+            nonces.rotated_at = now();
+        }
+        nonces.current.clone()
+    }
+
+    fn is_valid_nonce(&self, nonce: &str) -> bool {
+        let current = self.nonce();
+        let nonces = self.nonces.lock().expect("nonce lock poisoned");
+        nonce == current || nonce == nonces.previous
+    }
+
+    /// Records the proof, returning false when it was seen before
+    fn is_first_use(&self, jti: &str) -> bool {
+        let now = now();
+        let mut seen = self.seen.lock().expect("jti lock poisoned");
+        seen.retain(|_, expires_at| *expires_at > now);
+        seen.insert(jti.to_string(), now + 2 * PROOF_LIFETIME)
+            .is_none()
+    }
+
+    fn error(&self, kind: DpopErrorKind) -> DpopError {
+        DpopError::new(kind, self.nonce())
+    }
+
+    /// Verifies the proof, bound to the access token when one is presented with it,
+    /// and returns the thumbprint of the key it was signed with
+    pub fn verify(
+        &self,
+        proof: &DpopProof,
+        access_token: Option<&str>,
+    ) -> Result<String, DpopError> {
+        let jwt = proof
+            .proof
+            .as_ref()
+            .ok_or_else(|| self.error(DpopErrorKind::InvalidDpopProof))?;
+        let (payload, jwk) = decode(jwt).map_err(|e| {
+            log::info!("invalid DPoP proof: {}", e);
+            self.error(DpopErrorKind::InvalidDpopProof)
+        })?;
+        let claim = |name: &str| payload.claim(name).and_then(|v| v.as_str());
+        let iat = payload
+            .issued_at()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|t| t.as_secs())
+            .unwrap_or(0);
+        let ath = access_token.map(|t| sha256_base64url(t.as_bytes()));
+        if claim("htm") != Some(proof.htm.as_str())
+            || claim("htu").map(without_query) != Some(proof.htu.as_str())
+            || now().abs_diff(iat) > PROOF_LIFETIME
+            || claim("ath") != ath.as_deref()
+        {
+            return Err(self.error(DpopErrorKind::InvalidDpopProof));
+        }
+        if !claim("nonce")
+            .map(|n| self.is_valid_nonce(n))
+            .unwrap_or(false)
+        {
+            return Err(self.error(DpopErrorKind::UseDpopNonce));
+        }
+        let jkt = thumbprint(&jwk).ok_or_else(|| self.error(DpopErrorKind::InvalidDpopProof))?;
+        let jti = payload
+            .jwt_id()
+            .ok_or_else(|| self.error(DpopErrorKind::InvalidDpopProof))?;
+        if !self.is_first_use(&format!("{}:{}", jkt, jti)) {
+            return Err(self.error(DpopErrorKind::InvalidDpopProof));
+        }
+        Ok(jkt)
+    }
+}
+
+/// `htu` is compared without its query and fragment
+fn without_query(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or(uri)
+}
+
+/// Checks the proof is signed with the public key in its header
+fn decode(proof: &str) -> anyhow::Result<(JwtPayload, Jwk)> {
+    let header = jwt::decode_header(proof)?;
+    if header.claim("typ").and_then(|t| t.as_str()) != Some("dpop+jwt") {
+        return Err(anyhow::anyhow!("typ is not dpop+jwt"));
+    }
+    let alg = header
+        .claim("alg")
+        .and_then(|a| a.as_str())
+        .ok_or(anyhow::anyhow!("no alg"))?;
+    let jwk = match header.claim("jwk") {
+        Some(Value::Object(jwk)) if !jwk.contains_key("d") => Jwk::from_map(jwk.clone())?,
+        _ => return Err(anyhow::anyhow!("no public key")),
+    };
+    let verifier = jws_verifier(alg, &jwk)?;
+    let (payload, _) = jwt::decode_with_verifier(proof, verifier.as_ref())?;
+    Ok((payload, jwk))
+}
+
+/// The JWK SHA-256 thumbprint (`jkt`) access tokens are bound to
+/// https://www.rfc-editor.org/rfc/rfc7638.html
+pub fn thumbprint(jwk: &Jwk) -> Option<String> {
+    let member = |name: &str| jwk.parameter(name).and_then(|v| v.as_str());
+    // the required members in lexicographic order, without whitespace
+    let canonical = match jwk.key_type() {
+        "RSA" => format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            member("e")?,
+            member("n")?
+        ),
+        "EC" => format!(
+            r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+            member("crv")?,
+            member("x")?,
+            member("y")?
+        ),
+        _ => return None,
+    };
+    Some(sha256_base64url(canonical.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use josekit::jws::{JwsHeader, ES256};
+
+    struct Key {
+        jwk: Jwk,
+        der: Vec<u8>,
+    }
+
+    fn key() -> Key {
+        let key_pair = ES256.generate_key_pair().unwrap();
+        Key {
+            jwk: key_pair.to_jwk_public_key(),
+            der: key_pair.to_der_private_key(),
+        }
+    }
+
+    fn proof_for(key: &Key, htm: &str, nonce: Option<&str>, ath: Option<&str>) -> DpopProof {
+        let mut header = JwsHeader::new();
+        header.set_token_type("dpop+jwt");
+        header.set_jwk(key.jwk.clone());
+        let mut payload = JwtPayload::new();
+        payload.set_jwt_id(generate_challenge());
+        payload.set_issued_at(&SystemTime::now());
+        payload.set_claim("htm", Some(json!(htm))).unwrap();
+        payload
+            .set_claim("htu", Some(json!(format!("{}/token", ISSUER))))
+            .unwrap();
+        if let Some(nonce) = nonce {
+            payload.set_claim("nonce", Some(json!(nonce))).unwrap();
+        }
+        if let Some(ath) = ath {
+            payload.set_claim("ath", Some(json!(ath))).unwrap();
+        }
+        let signer = ES256.signer_from_der(&key.der).unwrap();
+        DpopProof {
+            proof: Some(jwt::encode_with_signer(&payload, &header, &signer).unwrap()),
+            htm: "POST".to_string(),
+            htu: format!("{}/token", ISSUER),
+        }
+    }
+
+    #[test]
+    fn verify_ok() {
+        let state = DpopState::default();
+        let key = key();
+        let proof = proof_for(&key, "POST", Some(&state.nonce()), None);
+        assert_eq!(
+            Ok(thumbprint(&key.jwk).unwrap()),
+            state.verify(&proof, None).map_err(|e| e.kind)
+        );
+        let ath = sha256_base64url(b"token");
+        let proof = proof_for(&key, "POST", Some(&state.nonce()), Some(&ath));
+        assert!(state.verify(&proof, Some("token")).is_ok());
+    }
+
+    #[test]
+    fn verify_ng() {
+        let state = DpopState::default();
+        let key = key();
+        let kind = |proof: &DpopProof, token| state.verify(proof, token).unwrap_err().kind;
+        assert_eq!(
+            DpopErrorKind::UseDpopNonce,
+            kind(&proof_for(&key, "POST", None, None), None)
+        );
+        assert_eq!(
+            DpopErrorKind::UseDpopNonce,
+            kind(&proof_for(&key, "POST", Some("stale"), None), None)
+        );
+        assert_eq!(
+            DpopErrorKind::InvalidDpopProof,
+            kind(&proof_for(&key, "GET", Some(&state.nonce()), None), None)
+        );
+        // bound to another access token
+        let ath = sha256_base64url(b"other");
+        assert_eq!(
+            DpopErrorKind::InvalidDpopProof,
+            kind(
+                &proof_for(&key, "POST", Some(&state.nonce()), Some(&ath)),
+                Some("token")
+            )
+        );
+        // replayed
+        let proof = proof_for(&key, "POST", Some(&state.nonce()), None);
+        assert!(state.verify(&proof, None).is_ok());
+        assert_eq!(DpopErrorKind::InvalidDpopProof, kind(&proof, None));
+    }
+
+    #[test]
+    fn thumbprint_ok() {
+        // https://www.rfc-editor.org/rfc/rfc7638.html#section-3.1
+        let jwk = Jwk::from_bytes(
+            r#"{"kty":"RSA","e":"AQAB","alg":"RS256","kid":"2011-04-29","n":"0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some("NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs".to_string()),
+            thumbprint(&jwk)
+        );
+    }
+}
@@ -8,7 +8,11 @@ use rocket::{
 use rocket_dyn_templates::Template;
 use thiserror::Error;
 
-use crate::{context::ErrorContext, message::authentication::ErrorAuthenticationResponse};
+use crate::{
+    context::ErrorContext,
+    dpop::DpopError,
+    message::{authentication::ErrorAuthenticationResponse, par::ErrorPushedAuthorizationResponse},
+};
 
 #[derive(Debug, Error)]
 pub enum CustomError {
@@ -22,12 +26,22 @@ pub enum CustomError {
     SessionError,
     #[error("Challenge error")]
     ChallengeError,
+    /// the redirect_uri of an authentication request isn't registered for the client,
+    /// so the error can't be sent back to it
+    #[error("Redirect URI error")]
+    RedirectUriError,
     #[error("Unauthorized error")]
     UnauthorizedError,
     #[error("JWT error")]
     JWTError(#[from] jsonwebtoken::errors::Error),
+    #[error("Server error: {0}")]
+    ServerError(String),
     #[error("Authentication Error")]
-    AuthenticationError(ErrorAuthenticationResponse),
+    AuthenticationError(Box<ErrorAuthenticationResponse>),
+    #[error("Pushed Authorization Error")]
+    PushedAuthorizationError(ErrorPushedAuthorizationResponse),
+    #[error("DPoP error")]
+    DpopError(#[from] DpopError),
 }
 
 impl<'r> Responder<'r, 'static> for CustomError {
@@ -47,15 +61,19 @@ impl<'r> Responder<'r, 'static> for CustomError {
                 Ok(res)
             }
             Self::ValidationError(template) => template.respond_to(request),
-            Self::SessionError => Template::render(
-                "error",
-                &ErrorContext {
-                    error_msg: String::from(
-                        "Session doesn't exist or is invalid. Please retry from login page.",
-                    ),
-                },
-            )
-            .respond_to(request),
+            Self::SessionError => {
+                let mut res = Template::render(
+                    "error",
+                    &ErrorContext {
+                        error_msg: String::from(
+                            "Session doesn't exist or is invalid. Please retry from login page.",
+                        ),
+                    },
+                )
+                .respond_to(request)?;
+                res.set_status(Status::Unauthorized);
+                Ok(res)
+            }
             Self::ChallengeError => Template::render(
                 "error",
                 &ErrorContext {
@@ -63,6 +81,19 @@ impl<'r> Responder<'r, 'static> for CustomError {
                 },
             )
             .respond_to(request),
+            Self::RedirectUriError => {
+                let mut res = Template::render(
+                    "error",
+                    &ErrorContext {
+                        error_msg: String::from(
+                            "redirect_uri is missing or isn't registered for the client.",
+                        ),
+                    },
+                )
+                .respond_to(request)?;
+                res.set_status(Status::BadRequest);
+                Ok(res)
+            }
             Self::UnauthorizedError => {
                 let res = Response::build().status(Status::Unauthorized).finalize();
                 Ok(res)
@@ -76,7 +107,18 @@ impl<'r> Responder<'r, 'static> for CustomError {
                     .finalize();
                 Ok(res)
             }
+            Self::ServerError(e) => {
+                let body = format!("Internal error: {}", e);
+                let res = Response::build()
+                    .status(Status::InternalServerError)
+                    .header(ContentType::Plain)
+                    .sized_body(body.len(), Cursor::new(body))
+                    .finalize();
+                Ok(res)
+            }
             Self::AuthenticationError(e) => e.respond_to(request),
+            Self::PushedAuthorizationError(e) => e.respond_to(request),
+            Self::DpopError(e) => e.respond_to(request),
         }
     }
 }
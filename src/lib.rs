@@ -6,11 +6,22 @@ extern crate diesel;
 #[macro_use]
 extern crate rocket_sync_db_pools;
 
+pub mod acr;
+pub mod backchannel;
+pub mod cbor;
+pub mod config;
 pub mod context;
+pub mod dpop;
 pub mod error;
+pub mod lockout;
 pub mod message;
+pub mod mfa;
 pub mod models;
+pub mod mtls;
+pub mod redirect;
 pub mod repository;
 pub mod schema;
 pub mod server;
+pub mod session;
 pub mod utils;
+pub mod webauthn;
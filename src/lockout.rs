@@ -0,0 +1,182 @@
+use std::net::IpAddr;
+
+use chrono::NaiveDateTime;
+use diesel::{result::Error::NotFound, MysqlConnection, QueryResult};
+use rocket::{
+    request::{self, FromRequest, Outcome},
+    Request,
+};
+
+use crate::{
+    config::{ProxyConfig, SessionConfig},
+    models::LoginFailure,
+    repository,
+};
+
+/// failures counted per entered username
+pub const ACCOUNT: &str = "account";
+/// failures counted per client IP address
+pub const IP: &str = "ip";
+
+/// ClientIp is the address failed logins are counted against.
+/// Unlike Rocket's `IpAddr` guard it ignores `X-Real-IP`, which any client can set,
+/// and only reads a header when `ProxyConfig` says a trusted proxy sets it.
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let header = request
+            .rocket()
+            .state::<ProxyConfig>()
+            .and_then(|c| c.client_ip_header.as_ref());
+        let ip = match header {
+            // the last address of a list such as X-Forwarded-For is the one the proxy added
+            Some(h) => request
+                .headers()
+                .get(h)
+                .flat_map(|v| v.split(','))
+                .last()
+                .and_then(|ip| ip.trim().parse().ok()),
+            None => request.remote().map(|addr| addr.ip()),
+        };
+        Outcome::Success(ClientIp(ip))
+    }
+}
+
+fn find_or_new(scope: &str, subject: &str, conn: &MysqlConnection) -> QueryResult<LoginFailure> {
+    match repository::find_login_failure(scope, subject, conn) {
+        Err(NotFound) => Ok(LoginFailure::new(scope, subject)),
+        res => res,
+    }
+}
+
+fn subjects(username: &str, ip: Option<IpAddr>) -> Vec<(&'static str, String)> {
+    let mut subjects = vec![(ACCOUNT, username.to_string())];
+    if let Some(ip) = ip {
+        subjects.push((IP, ip.to_string()));
+    }
+    subjects
+}
+
+/// Until when logging in as `username` from `ip` is refused, if it is.
+/// Accounts back off exponentially between failures; IP addresses, which may be
+/// shared behind a NAT, are only held back by a lockout.
+pub fn blocked_until(
+    username: &str,
+    ip: Option<IpAddr>,
+    config: &SessionConfig,
+    conn: &MysqlConnection,
+) -> QueryResult<Option<NaiveDateTime>> {
+    let mut until = vec![];
+    for (scope, subject) in subjects(username, ip) {
+        let failure = find_or_new(scope, &subject, conn)?;
+        let backoff_base = if scope == ACCOUNT {
+            config.login_backoff_base
+        } else {
+            0
+        };
+        until.push(failure.blocked_until(backoff_base, config.lockout_duration));
+    }
+    Ok(until.into_iter().flatten().max())
+}
+
+/// Counts a failed login against the account and the IP address and reports
+/// every new lockout on the `security` log target as a JSON event.
+/// Returns the end of the lockout if this failure triggered one.
+pub fn record_failure(
+    username: &str,
+    ip: Option<IpAddr>,
+    config: &SessionConfig,
+    conn: &MysqlConnection,
+) -> QueryResult<Option<NaiveDateTime>> {
+    let mut until = vec![];
+    for (scope, subject) in subjects(username, ip) {
+        let mut failure = find_or_new(scope, &subject, conn)?;
+        let threshold = if scope == ACCOUNT {
+            config.lockout_account_threshold
+        } else {
+            config.lockout_ip_threshold
+        };
+        if failure.record_failure(threshold, config.lockout_duration) {
+            log::warn!(
+                target: "security",
+                "{}",
+                serde_json::json!({
+                    "event": "login_lockout",
+                    "scope": scope,
+                    "subject": subject,
+                    "threshold": threshold,
+                    "locked_until": failure.locked_until,
+                })
+            );
+            until.push(failure.locked_until);
+        }
+        repository::save_login_failure(failure, conn)?;
+    }
+    Ok(until.into_iter().flatten().max())
+}
+
+/// Forgets the account's failures after a successful login
+pub fn clear(username: &str, conn: &MysqlConnection) -> QueryResult<usize> {
+    repository::delete_login_failure(ACCOUNT, username, conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{
+        fairing::AdHoc,
+        figment::Figment,
+        http::{Header, Method},
+        local::blocking::Client,
+        route::{self, BoxFuture},
+        Config, Data, Route,
+    };
+
+    /// Responds with the address the guard resolved
+    fn client_ip<'r>(request: &'r Request<'_>, _: Data) -> BoxFuture<'r> {
+        Box::pin(async move {
+            let ip = match request.guard::<ClientIp>().await {
+                Outcome::Success(ClientIp(Some(ip))) => ip.to_string(),
+                _ => String::new(),
+            };
+            route::Outcome::from(request, ip)
+        })
+    }
+
+    fn client(figment: Figment) -> Client {
+        let rocket = rocket::custom(figment)
+            .mount("/", vec![Route::new(Method::Get, "/", client_ip)])
+            .attach(AdHoc::config::<ProxyConfig>());
+        Client::tracked(rocket).expect("valid rocket instance")
+    }
+
+    #[test]
+    fn client_ip_ignores_spoofed_headers() {
+        let client = client(Config::figment());
+        // a new X-Real-IP on every attempt must not start a new count
+        for spoofed in ["203.0.113.1", "203.0.113.2"] {
+            let res = client
+                .get("/")
+                .remote("192.0.2.10:50000".parse().unwrap())
+                .header(Header::new("X-Real-IP", spoofed))
+                .header(Header::new("X-Forwarded-For", spoofed))
+                .dispatch();
+            assert_eq!("192.0.2.10", res.into_string().unwrap());
+        }
+    }
+
+    #[test]
+    fn client_ip_from_trusted_proxy_header() {
+        let client = client(Config::figment().merge(("client_ip_header", "X-Forwarded-For")));
+        let res = client
+            .get("/")
+            .remote("10.0.0.1:50000".parse().unwrap())
+            .header(Header::new("X-Forwarded-For", "203.0.113.1, 192.0.2.10"))
+            .dispatch();
+        assert_eq!("192.0.2.10", res.into_string().unwrap());
+    }
+}
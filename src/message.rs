@@ -1,7 +1,16 @@
+pub mod account;
 pub mod authentication;
+pub mod claims;
 pub mod client;
 pub mod consent;
+pub mod discovery;
 pub mod enums;
+pub mod introspection;
+pub mod jarm;
 pub mod login;
+pub mod logout;
+pub mod par;
+pub mod request_object;
 pub mod token;
 pub mod userinfo;
+pub mod webauthn;
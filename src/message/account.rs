@@ -0,0 +1,22 @@
+#[derive(FromForm)]
+pub struct RevokeGrantParams {
+    pub client_id: String,
+    pub csrf_token: String,
+}
+
+#[derive(FromForm)]
+pub struct LogoutOtherSessionsParams {
+    pub csrf_token: String,
+}
+
+#[derive(FromForm)]
+pub struct ConfirmTotpParams {
+    pub code: String,
+    pub csrf_token: String,
+}
+
+/// Creates the pending TOTP secret shown until the enrollment is confirmed
+#[derive(FromForm)]
+pub struct SetupTotpParams {
+    pub csrf_token: String,
+}
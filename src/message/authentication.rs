@@ -5,12 +5,23 @@ use crate::{error::CustomError, models::Client};
 use anyhow::Result;
 use rocket::{
     http::{hyper::header::LOCATION, Header, Status},
-    response::Responder,
+    response::{Redirect, Responder},
     Request, Response,
 };
-use serde::Serialize;
+use rocket_dyn_templates::Template;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use super::enums::{ResponseTypes, Scopes};
+use crate::{
+    context::{FormPostContext, FormPostParam},
+    redirect::RedirectBuilder,
+};
+
+use super::{
+    claims::ClaimsRequest,
+    enums::{ResponseMode, ResponseTypes, Scopes},
+    jarm::Jarm,
+};
 
 /// AuthenticationRequest represents a authentication request
 /// https://openid.net/specs/openid-connect-core-1_0.html#AuthRequest
@@ -21,15 +32,19 @@ pub struct AuthenticationRequest {
     redirect_uri: String,
     state: Option<String>,
     nonce: Option<String>,
+    max_age: Option<u64>,
+    response_mode: ResponseMode,
+    /// requested `acr` values in the order of preference
+    acr_values: Vec<String>,
+    /// whether `acr` was requested as an essential claim, which fails the
+    /// request when none of the values can be satisfied
+    acr_essential: bool,
+    claims: Option<ClaimsRequest>,
     // display: String,
     // prompt: String,
-    // max_age: u64,
     // ui_locales: String,
     // id_token_hint: String,
     // login_hint: String,
-    // acr_values: String,
-    // Claims https://openid.net/specs/openid-connect-core-1_0.html#ClaimsParameter
-    // claims: Claims,
 }
 
 impl AuthenticationRequest {
@@ -57,91 +72,197 @@ impl AuthenticationRequest {
         &self.nonce
     }
 
-    pub fn new(
-        scope: &str,
-        response_type: &str,
-        client_id: &str,
-        redirect_uri: &str,
-        state: &Option<String>,
-        nonce: &Option<String>,
-    ) -> Result<Self, CustomError> {
+    pub fn max_age(&self) -> &Option<u64> {
+        &self.max_age
+    }
+
+    pub fn response_mode(&self) -> &ResponseMode {
+        &self.response_mode
+    }
+
+    pub fn acr_values(&self) -> &Vec<String> {
+        &self.acr_values
+    }
+
+    pub fn acr_essential(&self) -> bool {
+        self.acr_essential
+    }
+
+    pub fn claims(&self) -> &Option<ClaimsRequest> {
+        &self.claims
+    }
+
+    /// Restores a request validated earlier, such as one saved with its challenge
+    pub fn new(param: AuthenticationRequestParam) -> Result<Self, CustomError> {
+        let redirect_uri = param.redirect_uri.unwrap_or_default();
+        let state = param.state;
+        let error = |error: AuthorizationError| {
+            CustomError::AuthenticationError(Box::new(ErrorAuthenticationResponse::new(
+                &redirect_uri,
+                error,
+                &state,
+            )))
+        };
+        let scope = Scopes::from_str(param.scope.as_deref().unwrap_or(""))
+            .or(Err(error(AuthorizationError::InvalidScope)))?;
+        let response_type = ResponseTypes::from_str(param.response_type.as_deref().unwrap_or(""))
+            .or(Err(error(AuthorizationError::UnsupportedResponseType)))?;
+        let response_mode = ResponseMode::from_str(param.response_mode.as_deref().unwrap_or(""))
+            .or(Err(error(AuthorizationError::InvalidRequest)))?;
         Ok(Self {
-            scope: Scopes::from_str(scope).or(Err(CustomError::AuthenticationError(
-                ErrorAuthenticationResponse::new(
-                    redirect_uri,
-                    AuthorizationError::InvalidScope,
-                    state,
-                ),
-            )))?,
-            response_type: ResponseTypes::from_str(response_type).or(Err(
-                CustomError::AuthenticationError(ErrorAuthenticationResponse::new(
-                    redirect_uri,
-                    AuthorizationError::UnsupportedResponseType,
-                    state,
-                )),
-            ))?,
-            client_id: client_id.to_string(),
-            redirect_uri: redirect_uri.to_string(),
-            state: state.to_owned(),
-            nonce: nonce.to_owned(),
+            scope,
+            response_type,
+            client_id: param.client_id.unwrap_or_default(),
+            redirect_uri,
+            state,
+            nonce: param.nonce,
+            max_age: param.max_age,
+            response_mode,
+            acr_values: vec![],
+            acr_essential: false,
+            claims: None,
         })
     }
 
     pub fn from(param: AuthenticationRequestParam, client: &Client) -> Result<Self, CustomError> {
         let redirect_uri = param.redirect_uri.unwrap_or("".to_string());
-        let param_scope = param.scope.ok_or(CustomError::AuthenticationError(
-            ErrorAuthenticationResponse::new(
-                &redirect_uri,
-                AuthorizationError::InvalidRequest,
-                &param.state,
-            ),
-        ))?;
+        // checked first: every other error is sent to the redirect_uri
+        client
+            .check_redirect_uri(&redirect_uri)
+            .or(Err(CustomError::RedirectUriError))?;
+        let param_scope = param
+            .scope
+            .ok_or(CustomError::AuthenticationError(Box::new(
+                ErrorAuthenticationResponse::new(
+                    &redirect_uri,
+                    AuthorizationError::InvalidRequest,
+                    &param.state,
+                ),
+            )))?;
         let scope = Scopes::from_str(&param_scope).or(Err(CustomError::AuthenticationError(
-            ErrorAuthenticationResponse::new(
+            Box::new(ErrorAuthenticationResponse::new(
                 &redirect_uri,
                 AuthorizationError::InvalidScope,
                 &param.state,
-            ),
+            )),
         )))?;
         client
             .check_scopes(&scope)
-            .or(Err(CustomError::AuthenticationError(
+            .or(Err(CustomError::AuthenticationError(Box::new(
                 ErrorAuthenticationResponse::new(
                     &redirect_uri,
                     AuthorizationError::InvalidScope,
                     &param.state,
                 ),
-            )))?;
-        let param_res_type = param.response_type.ok_or(CustomError::AuthenticationError(
-            ErrorAuthenticationResponse::new(
-                &redirect_uri,
-                AuthorizationError::InvalidRequest,
-                &param.state,
-            ),
-        ))?;
+            ))))?;
+        let param_res_type =
+            param
+                .response_type
+                .ok_or(CustomError::AuthenticationError(Box::new(
+                    ErrorAuthenticationResponse::new(
+                        &redirect_uri,
+                        AuthorizationError::InvalidRequest,
+                        &param.state,
+                    ),
+                )))?;
         let response_type = ResponseTypes::from_str(&param_res_type).or(Err(
-            CustomError::AuthenticationError(ErrorAuthenticationResponse::new(
-                "hoge",
+            CustomError::AuthenticationError(Box::new(ErrorAuthenticationResponse::new(
+                &redirect_uri,
                 AuthorizationError::UnsupportedResponseType,
                 &param.state,
-            )),
+            ))),
         ))?;
-        client
-            .check_restypes(&response_type)
-            .or(Err(CustomError::AuthenticationError(
+        if !response_type.is_supported() {
+            return Err(CustomError::AuthenticationError(Box::new(
                 ErrorAuthenticationResponse::new(
                     &redirect_uri,
                     AuthorizationError::UnsupportedResponseType,
                     &param.state,
                 ),
-            )))?;
-        let param_client_id = param.client_id.ok_or(CustomError::AuthenticationError(
+            )));
+        }
+        let default_mode = response_type.default_response_mode();
+        let response_mode = match param.response_mode.as_deref() {
+            // "jwt" is the JARM shorthand for the response type's default response mode
+            Some("jwt") => Some(default_mode.jwt()),
+            Some(mode) => ResponseMode::from_str(mode).ok(),
+            None => Some(default_mode),
+        }
+        .filter(|m| {
+            response_type.allows_response_mode(m)
+                // tokens may be carried in query.jwt only when the response is encrypted
+                || (m == &ResponseMode::QueryJwt && Jarm::new(client).is_encrypted())
+        })
+        .ok_or(CustomError::AuthenticationError(Box::new(
             ErrorAuthenticationResponse::new(
                 &redirect_uri,
                 AuthorizationError::InvalidRequest,
                 &param.state,
+            )
+            .response_mode(default_mode),
+        )))?;
+        client
+            .check_restypes(&response_type)
+            .or(Err(CustomError::AuthenticationError(Box::new(
+                ErrorAuthenticationResponse::new(
+                    &redirect_uri,
+                    AuthorizationError::UnsupportedResponseType,
+                    &param.state,
+                )
+                .response_mode(response_mode)
+                .jarm(Jarm::new(client)),
+            ))))?;
+        if response_type.requires_nonce() && param.nonce.is_none() {
+            return Err(CustomError::AuthenticationError(Box::new(
+                ErrorAuthenticationResponse::new(
+                    &redirect_uri,
+                    AuthorizationError::InvalidRequest,
+                    &param.state,
+                )
+                .response_mode(response_mode)
+                .jarm(Jarm::new(client)),
+            )));
+        }
+        let param_client_id = param
+            .client_id
+            .ok_or(CustomError::AuthenticationError(Box::new(
+                ErrorAuthenticationResponse::new(
+                    &redirect_uri,
+                    AuthorizationError::InvalidRequest,
+                    &param.state,
+                )
+                .response_mode(response_mode)
+                .jarm(Jarm::new(client)),
+            )))?;
+        let claims = match &param.claims {
+            Some(claims) => Some(
+                ClaimsRequest::from_str(claims).or(Err(CustomError::AuthenticationError(
+                    Box::new(
+                        ErrorAuthenticationResponse::new(
+                            &redirect_uri,
+                            AuthorizationError::InvalidRequest,
+                            &param.state,
+                        )
+                        .response_mode(response_mode)
+                        .jarm(Jarm::new(client)),
+                    ),
+                )))?,
             ),
-        ))?;
+            None => None,
+        };
+        let acr_claim = claims.as_ref().and_then(|c| c.acr());
+        let acr_essential = acr_claim.as_ref().filter(|c| c.essential).is_some();
+        // values in the claim request take precedence over acr_values
+        let acr_values = match acr_claim.map(|c| c.string_values()) {
+            Some(values) if !values.is_empty() => values,
+            _ => param
+                .acr_values
+                .as_deref()
+                .unwrap_or("")
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+        };
 
         Ok(AuthenticationRequest {
             scope,
@@ -150,11 +271,17 @@ impl AuthenticationRequest {
             redirect_uri,
             state: param.state.map(|s| s.to_string()),
             nonce: param.nonce.map(|s| s.to_string()),
+            max_age: param.max_age,
+            response_mode,
+            acr_values,
+            acr_essential,
+            claims,
         })
     }
 }
 
-#[derive(FromForm, Clone)]
+/// Also stored as JSON for pushed authorization requests
+#[derive(FromForm, Clone, Serialize, Deserialize)]
 pub struct AuthenticationRequestParam {
     pub scope: Option<String>,
     pub response_type: Option<String>,
@@ -162,49 +289,207 @@ pub struct AuthenticationRequestParam {
     pub redirect_uri: Option<String>,
     pub state: Option<String>,
     pub nonce: Option<String>,
+    pub max_age: Option<u64>,
+    pub response_mode: Option<String>,
+    pub acr_values: Option<String>,
+    /// JSON, see `ClaimsRequest`
+    pub claims: Option<String>,
+    /// the request object by value, see `request_object::decode`
+    pub request: Option<String>,
+    /// where to fetch the request object from
+    pub request_uri: Option<String>,
     // display: String,
     // prompt: String,
-    // max_age: u64,
     // ui_locales: String,
     // id_token_hint: String,
     // login_hint: String,
-    // acr_values: String,
-    // Claims https://openid.net/specs/openid-connect-core-1_0.html#ClaimsParameter
-    // claims: Claims,
+}
+
+impl AuthenticationRequestParam {
+    /// Merges the parameters of a request object into the query's,
+    /// the request object's taking precedence
+    /// https://openid.net/specs/openid-connect-core-1_0.html#RequestObject
+    pub fn with_request_object(self, object: AuthenticationRequestParam) -> Self {
+        Self {
+            scope: object.scope.or(self.scope),
+            response_type: object.response_type.or(self.response_type),
+            client_id: object.client_id.or(self.client_id),
+            redirect_uri: object.redirect_uri.or(self.redirect_uri),
+            state: object.state.or(self.state),
+            nonce: object.nonce.or(self.nonce),
+            max_age: object.max_age.or(self.max_age),
+            response_mode: object.response_mode.or(self.response_mode),
+            acr_values: object.acr_values.or(self.acr_values),
+            claims: object.claims.or(self.claims),
+            request: None,
+            request_uri: None,
+        }
+    }
 }
 
 /// SuccessfulAuthenticationResponse represents a successful authentication response
 /// https://openid.net/specs/openid-connect-core-1_0.html#AuthResponse
+/// https://openid.net/specs/openid-connect-core-1_0.html#ImplicitAuthResponse
+/// https://openid.net/specs/openid-connect-core-1_0.html#HybridAuthResponse
 #[derive(Serialize)]
 pub struct SuccessfulAuthenticationResponse {
     next: String,
-    code: String,
+    code: Option<String>,
+    access_token: Option<String>,
+    token_type: Option<String>,
+    expires_in: Option<u64>,
+    id_token: Option<String>,
     state: Option<String>,
+    session_state: Option<String>,
+    #[serde(skip)]
+    response_mode: ResponseMode,
+    #[serde(skip)]
+    jarm: Option<Jarm>,
 }
 
 impl SuccessfulAuthenticationResponse {
-    pub fn new(next: &str, code: &str, state: &Option<String>) -> Self {
+    pub fn new(next: &str, state: &Option<String>, response_mode: ResponseMode) -> Self {
         Self {
             next: next.to_string(),
-            code: code.to_string(),
+            code: None,
+            access_token: None,
+            token_type: None,
+            expires_in: None,
+            id_token: None,
             state: state.to_owned(),
+            session_state: None,
+            response_mode,
+            jarm: None,
         }
     }
+
+    /// Attaches what is needed to wrap the response in a JWT for the JARM response modes
+    pub fn jarm(mut self, jarm: Jarm) -> Self {
+        self.jarm = Some(jarm);
+        self
+    }
+
+    pub fn code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    pub fn access_token(mut self, access_token: &str, expires_in: u64) -> Self {
+        self.access_token = Some(access_token.to_string());
+        self.token_type = Some(String::from("Bearer"));
+        self.expires_in = Some(expires_in);
+        self
+    }
+
+    pub fn id_token(mut self, id_token: &str) -> Self {
+        self.id_token = Some(id_token.to_string());
+        self
+    }
+
+    pub fn session_state(mut self, session_state: &str) -> Self {
+        self.session_state = Some(session_state.to_string());
+        self
+    }
 }
 
 impl<'r> Responder<'r, 'static> for SuccessfulAuthenticationResponse {
-    fn respond_to(self, _request: &'r Request<'_>) -> rocket::response::Result<'static> {
-        let mut next = format!("{}?code={}", self.next, self.code);
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let mut params = vec![];
+        if let Some(c) = self.code {
+            params.push(("code", Value::from(c)));
+        }
+        if let Some(t) = self.access_token {
+            params.push(("access_token", Value::from(t)));
+        }
+        if let Some(t) = self.token_type {
+            params.push(("token_type", Value::from(t)));
+        }
+        if let Some(e) = self.expires_in {
+            params.push(("expires_in", Value::from(e)));
+        }
+        if let Some(t) = self.id_token {
+            params.push(("id_token", Value::from(t)));
+        }
         if let Some(s) = self.state {
-            next = format!("{}&state={}", next, s);
+            params.push(("state", Value::from(s)));
+        }
+        if let Some(s) = self.session_state {
+            params.push(("session_state", Value::from(s)));
         }
-        Response::build()
-            .status(Status::Found)
-            .header(Header::new(LOCATION.as_str(), next))
-            .ok()
+        respond_with_mode(&self.next, params, self.response_mode, self.jarm, request)
     }
 }
 
+/// Returns the authorization response parameters to the client
+/// in the way the response_mode specifies. The parameters keep their JSON
+/// types in a JARM response and are sent as strings otherwise.
+fn respond_with_mode(
+    next: &str,
+    params: Vec<(&str, Value)>,
+    response_mode: ResponseMode,
+    jarm: Option<Jarm>,
+    request: &Request<'_>,
+) -> rocket::response::Result<'static> {
+    let params = if response_mode.is_jwt() {
+        let response = jarm
+            .ok_or(Status::InternalServerError)?
+            .wrap(&params)
+            .map_err(|e| {
+                log::error!("failed to build the JARM response: {}", e);
+                Status::InternalServerError
+            })?;
+        vec![("response", response)]
+    } else {
+        params
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(s) => (name, s),
+                value => (name, value.to_string()),
+            })
+            .collect()
+    };
+    let redirect = params
+        .iter()
+        .fold(RedirectBuilder::new(next), |r, (name, value)| {
+            r.param(name, value)
+        });
+    let next = match response_mode.base() {
+        ResponseMode::Query => redirect.query(),
+        ResponseMode::Fragment => redirect.fragment(),
+        _ => {
+            return Template::render(
+                "form_post",
+                &FormPostContext {
+                    redirect_uri: next.to_string(),
+                    params: params
+                        .into_iter()
+                        .map(|(name, value)| FormPostParam {
+                            name: name.to_string(),
+                            value,
+                        })
+                        .collect(),
+                },
+            )
+            .respond_to(request);
+        }
+    };
+    Response::build()
+        .status(Status::Found)
+        .header(Header::new(LOCATION.as_str(), next))
+        .ok()
+}
+
+/// AuthenticateResponse represents the outcome of an authentication request.
+/// The login page is skipped when the browser already has a valid session,
+/// and only the second factor is asked for when the session needs a step-up.
+#[derive(Responder)]
+pub enum AuthenticateResponse {
+    Login(Template),
+    SecondFactor(Redirect),
+    Consent(Redirect),
+    Authorized(SuccessfulAuthenticationResponse),
+}
+
 /// AuthorizationError represents an error code for ErrorAuthenticationResponse
 #[derive(Debug)]
 pub enum AuthorizationError {
@@ -224,6 +509,7 @@ pub enum AuthorizationError {
     RequestNotSupported,
     RequestUriNotSupported,
     RegistrationNotSupported,
+    UnmetAuthenticationRequirements,
 }
 
 impl fmt::Display for AuthorizationError {
@@ -245,6 +531,9 @@ impl fmt::Display for AuthorizationError {
             AuthorizationError::RequestNotSupported => write!(f, "request_not_supported"),
             AuthorizationError::RequestUriNotSupported => write!(f, "request_uri_not_supported"),
             AuthorizationError::RegistrationNotSupported => write!(f, "registration_not_supported"),
+            AuthorizationError::UnmetAuthenticationRequirements => {
+                write!(f, "unmet_authentication_requirements")
+            }
         }
     }
 }
@@ -265,6 +554,10 @@ pub struct ErrorAuthenticationResponse {
     error_description: Option<String>,
     error_uri: Option<String>,
     state: Option<String>,
+    #[serde(skip)]
+    response_mode: ResponseMode,
+    #[serde(skip)]
+    jarm: Option<Jarm>,
 }
 
 impl ErrorAuthenticationResponse {
@@ -275,25 +568,206 @@ impl ErrorAuthenticationResponse {
             error_description: None,
             error_uri: None,
             state: state.to_owned(),
+            response_mode: ResponseMode::Query,
+            jarm: None,
         }
     }
+
+    /// Returns the error the same way as a successful response would be returned
+    pub fn response_mode(mut self, response_mode: ResponseMode) -> Self {
+        self.response_mode = response_mode;
+        self
+    }
+
+    /// Attaches what is needed to wrap the error in a JWT for the JARM response modes
+    pub fn jarm(mut self, jarm: Jarm) -> Self {
+        self.jarm = Some(jarm);
+        self
+    }
+
+    pub fn error(&self) -> &AuthorizationError {
+        &self.error
+    }
 }
 
 impl<'r> Responder<'r, 'static> for ErrorAuthenticationResponse {
-    fn respond_to(self, _request: &'r Request<'_>) -> rocket::response::Result<'static> {
-        let mut next = format!("{}?error={}", self.next, self.error);
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let mut params = vec![("error", Value::from(self.error.to_string()))];
         if let Some(desc) = self.error_description {
-            next = format!("{}&error_description={}", next, desc);
+            params.push(("error_description", Value::from(desc)));
         }
         if let Some(euri) = self.error_uri {
-            next = format!("{}&error_uri={}", next, euri);
+            params.push(("error_uri", Value::from(euri)));
         }
         if let Some(s) = self.state {
-            next = format!("{}&state={}", next, s);
+            params.push(("state", Value::from(s)));
+        }
+        respond_with_mode(&self.next, params, self.response_mode, self.jarm, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{
+        form::Form,
+        http::Method,
+        local::blocking::Client as LocalClient,
+        route::{self, BoxFuture},
+        Data, Route,
+    };
+
+    /// Registered for every response type the tests try
+    fn client() -> Client {
+        Client {
+            response_type: String::from("code id_token token"),
+            ..Client::for_test()
+        }
+    }
+
+    fn param(response_type: &str, nonce: Option<&str>) -> AuthenticationRequestParam {
+        AuthenticationRequestParam {
+            scope: Some(String::from("openid")),
+            response_type: Some(response_type.to_string()),
+            client_id: Some(String::from("client")),
+            redirect_uri: Some(String::from("https://client.example.com/cb")),
+            state: None,
+            nonce: nonce.map(String::from),
+            max_age: None,
+            response_mode: None,
+            acr_values: None,
+            claims: None,
+            request: None,
+            request_uri: None,
+        }
+    }
+
+    #[test]
+    fn authentication_request_nonce_ok() {
+        assert!(AuthenticationRequest::from(param("code", None), &client()).is_ok());
+        for response_type in ["id_token", "code id_token", "code token"] {
+            let req =
+                AuthenticationRequest::from(param(response_type, Some("n-0S6_WzA2Mj")), &client());
+            assert!(req.is_ok());
+        }
+    }
+
+    #[test]
+    fn authentication_request_nonce_ng() {
+        for response_type in ["id_token", "id_token token", "code id_token", "code token"] {
+            match AuthenticationRequest::from(param(response_type, None), &client()) {
+                Err(CustomError::AuthenticationError(e)) => {
+                    assert!(matches!(e.error(), AuthorizationError::InvalidRequest))
+                }
+                _ => panic!("{} without nonce must be rejected", response_type),
+            }
+        }
+    }
+
+    #[test]
+    fn authentication_request_redirect_uri_ng() {
+        for redirect_uri in [
+            None,
+            Some("https://evil.example.com/cb"),
+            // exact match only
+            Some("https://client.example.com/cb/"),
+            Some("https://client.example.com/cb?x=1"),
+        ] {
+            let mut param = param("code", None);
+            param.redirect_uri = redirect_uri.map(String::from);
+            // the redirect_uri is checked before anything that would be reported to it
+            param.scope = Some(String::from("unknown"));
+            assert!(matches!(
+                AuthenticationRequest::from(param, &client()),
+                Err(CustomError::RedirectUriError)
+            ));
+        }
+    }
+
+    /// Validates the query as an authentication request and responds with the outcome
+    fn authenticate<'r>(request: &'r Request<'_>, _: Data) -> BoxFuture<'r> {
+        Box::pin(async move {
+            let res = Form::<AuthenticationRequestParam>::parse_iter(request.query_fields())
+                .or(Err(CustomError::BadRequest))
+                .and_then(|param| AuthenticationRequest::from(param, &client()))
+                .map(|_| "ok");
+            route::Outcome::from(request, res)
+        })
+    }
+
+    fn local_client() -> LocalClient {
+        let rocket = rocket::build()
+            .mount("/", vec![Route::new(Method::Get, "/", authenticate)])
+            .attach(Template::fairing());
+        LocalClient::tracked(rocket).expect("valid rocket instance")
+    }
+
+    #[test]
+    fn unregistered_redirect_uri_is_not_redirected_to() {
+        let client = local_client();
+        let res = client
+            .get("/?client_id=client&response_type=code&scope=unknown&state=xyz&redirect_uri=https%3A%2F%2Fevil.example.com%2Fcb")
+            .dispatch();
+        assert_eq!(Status::BadRequest, res.status());
+        assert!(res.headers().get_one("Location").is_none());
+        assert!(!res.into_string().unwrap().contains("evil.example.com"));
+        // the same error is sent to a registered redirect_uri
+        let res = client
+            .get("/?client_id=client&response_type=code&scope=unknown&state=xyz&redirect_uri=https%3A%2F%2Fclient.example.com%2Fcb")
+            .dispatch();
+        assert_eq!(Status::Found, res.status());
+        assert_eq!(
+            Some("https://client.example.com/cb?error=invalid_scope&state=xyz"),
+            res.headers().get_one("Location")
+        );
+    }
+
+    #[test]
+    fn implicit_request_with_unregistered_redirect_uri_is_rejected() {
+        for response_type in ["id_token token", "code id_token token"] {
+            let mut param = param(response_type, Some("n-0S6_WzA2Mj"));
+            param.redirect_uri = Some(String::from("https://evil.example.com/cb"));
+            assert!(matches!(
+                AuthenticationRequest::from(param, &client()),
+                Err(CustomError::RedirectUriError)
+            ));
         }
-        Response::build()
-            .status(Status::Found)
-            .header(Header::new(LOCATION.as_str(), next))
-            .ok()
+        let client = local_client();
+        let res = client
+            .get("/?client_id=client&response_type=id_token%20token&scope=openid&nonce=n-0S6_WzA2Mj&redirect_uri=https%3A%2F%2Fevil.example.com%2Fcb")
+            .dispatch();
+        assert_eq!(Status::BadRequest, res.status());
+        assert!(res.headers().get_one("Location").is_none());
+    }
+
+    #[test]
+    fn form_post_to_unregistered_redirect_uri_is_rejected() {
+        let client = local_client();
+        // missing the nonce, which is reported through the form
+        let query =
+            "/?client_id=client&response_type=id_token&scope=openid&response_mode=form_post";
+        let res = client
+            .get(format!(
+                "{}&redirect_uri=https%3A%2F%2Fevil.example.com%2Fcb",
+                query
+            ))
+            .dispatch();
+        assert_eq!(Status::BadRequest, res.status());
+        let body = res.into_string().unwrap();
+        assert!(!body.contains("<form"));
+        assert!(!body.contains("evil.example.com"));
+        let res = client
+            .get(format!(
+                "{}&redirect_uri=https%3A%2F%2Fclient.example.com%2Fcb",
+                query
+            ))
+            .dispatch();
+        assert_eq!(Status::Ok, res.status());
+        let body = res.into_string().unwrap();
+        // tera escapes the slashes of the action
+        assert!(body.contains(
+            r#"<form method="post" action="https:&#x2F;&#x2F;client.example.com&#x2F;cb""#
+        ));
+        assert!(body.contains(r#"value="invalid_request""#));
     }
 }
@@ -0,0 +1,209 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Claims about the end-user, by name
+pub type Claims = Map<String, Value>;
+
+/// ClaimRequest represents the request for an individual claim
+/// https://openid.net/specs/openid-connect-core-1_0.html#IndividualClaimsRequests
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ClaimRequest {
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub essential: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<Value>>,
+}
+
+impl ClaimRequest {
+    /// Whether the claim's value is one the client asked for, if it asked for particular ones
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (&self.value, &self.values) {
+            (Some(v), _) => v == value,
+            (None, Some(values)) => values.contains(value),
+            (None, None) => true,
+        }
+    }
+
+    /// The requested values that are strings, `value` before `values`
+    pub fn string_values(&self) -> Vec<String> {
+        self.value
+            .iter()
+            .chain(self.values.iter().flatten())
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect()
+    }
+}
+
+/// ClaimsRequest represents the `claims` request parameter.
+/// A claim requested without any options is `null`, hence the `Option`.
+/// https://openid.net/specs/openid-connect-core-1_0.html#ClaimsParameter
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ClaimsRequest {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub userinfo: BTreeMap<String, Option<ClaimRequest>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub id_token: BTreeMap<String, Option<ClaimRequest>>,
+}
+
+impl FromStr for ClaimsRequest {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+impl fmt::Display for ClaimsRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", serde_json::to_string(self).or(Err(fmt::Error))?)
+    }
+}
+
+/// Picks the requested claims out of the end-user's, leaving out those whose
+/// value the client didn't ask for
+fn select(requested: &BTreeMap<String, Option<ClaimRequest>>, available: &Claims) -> Claims {
+    requested
+        .iter()
+        .filter_map(|(name, request)| {
+            let value = available.get(name)?;
+            match request {
+                Some(r) if !r.accepts(value) => None,
+                _ => Some((name.clone(), value.clone())),
+            }
+        })
+        .collect()
+}
+
+impl ClaimsRequest {
+    /// The request for the `acr` claim in the ID token
+    pub fn acr(&self) -> Option<ClaimRequest> {
+        self.id_token
+            .get("acr")
+            .map(|r| r.clone().unwrap_or_default())
+    }
+
+    /// The `sub` the client asked for; a response for anyone else must not be returned
+    pub fn sub(&self) -> Option<&Value> {
+        self.id_token
+            .get("sub")
+            .and_then(|r| r.as_ref())
+            .and_then(|r| r.value.as_ref())
+    }
+
+    pub fn requests_id_token_claim(&self, name: &str) -> bool {
+        self.id_token.contains_key(name)
+    }
+
+    /// Drops the requests for claims `keep` returns false for, in both members
+    pub fn retain<F: Fn(&str) -> bool>(&mut self, keep: F) {
+        self.userinfo.retain(|name, _| keep(name));
+        self.id_token.retain(|name, _| keep(name));
+    }
+
+    /// Names of the requested claims, in either member
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .userinfo
+            .keys()
+            .chain(self.id_token.keys())
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The end-user's claims to add to the ID token
+    pub fn id_token_claims(&self, available: &Claims) -> Claims {
+        select(&self.id_token, available)
+    }
+
+    /// The end-user's claims to add to the userinfo response
+    pub fn userinfo_claims(&self, available: &Claims) -> Claims {
+        select(&self.userinfo, available)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn available() -> Claims {
+        match json!({"name": "tarou tanaka", "email": "test@example.com", "email_verified": true}) {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn parse_ok() {
+        let claims = ClaimsRequest::from_str(
+            r#"{
+                "userinfo": {"email": {"essential": true}, "name": null},
+                "id_token": {"acr": {"values": ["urn:a", 1, "urn:b"]}, "sub": {"value": "userid"}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Some(&Some(ClaimRequest {
+                essential: true,
+                value: None,
+                values: None,
+            })),
+            claims.userinfo.get("email")
+        );
+        assert_eq!(Some(&None), claims.userinfo.get("name"));
+        assert_eq!(
+            vec!["urn:a", "urn:b"],
+            claims.acr().unwrap().string_values()
+        );
+        assert_eq!(Some(&json!("userid")), claims.sub());
+        assert_eq!(vec!["acr", "email", "name", "sub"], claims.names());
+        // persisted as JSON and read back
+        assert_eq!(
+            claims,
+            ClaimsRequest::from_str(&claims.to_string()).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_ng() {
+        assert!(ClaimsRequest::from_str("not json").is_err());
+        assert!(ClaimsRequest::from_str(r#"{"id_token": []}"#).is_err());
+        assert!(ClaimsRequest::from_str(r#"{"id_token": {"acr": {"essential": "yes"}}}"#).is_err());
+    }
+
+    #[test]
+    fn select_ok() {
+        let claims = ClaimsRequest::from_str(
+            r#"{
+                "userinfo": {"name": null, "phone_number": null},
+                "id_token": {"email": {"value": "other@example.com"}, "email_verified": {"values": [true]}}
+            }"#,
+        )
+        .unwrap();
+        let userinfo = claims.userinfo_claims(&available());
+        assert_eq!(Some(&json!("tarou tanaka")), userinfo.get("name"));
+        // the end-user has no phone number
+        assert_eq!(1, userinfo.len());
+        let id_token = claims.id_token_claims(&available());
+        assert_eq!(None, id_token.get("email"));
+        assert_eq!(Some(&json!(true)), id_token.get("email_verified"));
+        assert!(claims.acr().is_none());
+    }
+
+    #[test]
+    fn retain_ok() {
+        let mut claims = ClaimsRequest::from_str(
+            r#"{"userinfo": {"name": null, "email": null}, "id_token": {"email": null, "acr": null}}"#,
+        )
+        .unwrap();
+        claims.retain(|name| name != "email");
+        assert_eq!(vec!["acr", "name"], claims.names());
+    }
+}
@@ -3,4 +3,23 @@ pub struct ClientParams {
     pub scope: String,
     pub response_type: String,
     pub redirect_uri: String,
+    pub require_auth_time: Option<bool>,
+    pub jwks: Option<String>,
+    pub authorization_encrypted_response_alg: Option<String>,
+    pub authorization_encrypted_response_enc: Option<String>,
+    pub post_logout_redirect_uris: Option<String>,
+    pub backchannel_logout_uri: Option<String>,
+    pub backchannel_logout_session_required: Option<bool>,
+    pub frontchannel_logout_uri: Option<String>,
+    pub frontchannel_logout_session_required: Option<bool>,
+    pub request_object_signing_alg: Option<String>,
+    pub require_signed_request_object: Option<bool>,
+    pub request_uris: Option<String>,
+    pub require_pushed_authorization_requests: Option<bool>,
+    pub token_endpoint_auth_method: Option<String>,
+    pub tls_client_auth_subject_dn: Option<String>,
+    pub tls_client_auth_san_dns: Option<String>,
+    pub tls_client_certificate_bound_access_tokens: Option<bool>,
+    pub access_token_jwt: Option<bool>,
+    pub access_token_audience: Option<String>,
 }
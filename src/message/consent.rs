@@ -6,6 +6,8 @@ use rocket::{
     Request,
 };
 
+use super::enums::Scopes;
+
 #[derive(FromForm, Debug)]
 pub struct ConsentGetParams {
     pub consent_challenge: String,
@@ -25,7 +27,25 @@ impl<'r> FromRequest<'r> for ConsentGetParams {
 pub struct ConsentParams {
     pub consent: String,
     pub consent_challenge: String,
+    pub csrf_token: String,
     pub state: Option<String>,
+    pub scope: Vec<String>,
+}
+
+impl ConsentParams {
+    pub fn is_accepted(&self) -> bool {
+        self.consent == "ok"
+    }
+
+    /// Narrows the requested scopes down to the ones the end-user granted
+    pub fn granted_scopes(&self, requested: &Scopes) -> Scopes {
+        requested
+            .scopes
+            .iter()
+            .filter(|s| s.is_required() || self.scope.contains(&s.to_string()))
+            .cloned()
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -36,3 +56,38 @@ impl<'r> FromRequest<'r> for ConsentParams {
         Outcome::Success(param)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn granted_scopes_ok() {
+        let param = ConsentParams {
+            consent: String::from("ok"),
+            consent_challenge: String::default(),
+            csrf_token: String::default(),
+            state: None,
+            scope: vec![String::from("email"), String::from("phone")],
+        };
+        let requested = Scopes::from_str("openid profile email").unwrap();
+        let expected = Scopes::from_str("openid email").unwrap();
+        assert_eq!(expected, param.granted_scopes(&requested));
+    }
+
+    #[test]
+    fn granted_scopes_keeps_required() {
+        let param = ConsentParams {
+            consent: String::from("ok"),
+            consent_challenge: String::default(),
+            csrf_token: String::default(),
+            state: None,
+            scope: vec![],
+        };
+        let requested = Scopes::from_str("openid profile").unwrap();
+        let expected = Scopes::from_str("openid").unwrap();
+        assert_eq!(expected, param.granted_scopes(&requested));
+    }
+}
@@ -0,0 +1,141 @@
+use serde::Serialize;
+
+use crate::{
+    acr::AcrPolicy,
+    mtls::{SELF_SIGNED_TLS_CLIENT_AUTH, TLS_CLIENT_AUTH},
+    utils::JWS_ALGS,
+};
+
+use super::request_object;
+
+pub const ISSUER: &str = "http://example.com";
+
+/// ProviderMetadata represents the OpenID Provider configuration
+/// https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderMetadata
+#[derive(Serialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub pushed_authorization_request_endpoint: String,
+    pub require_pushed_authorization_requests: bool,
+    pub token_endpoint: String,
+    pub introspection_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub end_session_endpoint: String,
+    pub check_session_iframe: String,
+    pub jwks_uri: String,
+    pub scopes_supported: Vec<String>,
+    pub acr_values_supported: Vec<String>,
+    pub claims_parameter_supported: bool,
+    pub request_parameter_supported: bool,
+    pub request_uri_parameter_supported: bool,
+    pub require_request_uri_registration: bool,
+    pub request_object_signing_alg_values_supported: Vec<String>,
+    pub request_object_encryption_alg_values_supported: Vec<String>,
+    pub request_object_encryption_enc_values_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub response_modes_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub introspection_endpoint_auth_methods_supported: Vec<String>,
+    pub tls_client_certificate_bound_access_tokens: bool,
+    pub authorization_signing_alg_values_supported: Vec<String>,
+    pub authorization_encryption_alg_values_supported: Vec<String>,
+    pub authorization_encryption_enc_values_supported: Vec<String>,
+    pub backchannel_logout_supported: bool,
+    pub backchannel_logout_session_supported: bool,
+    pub frontchannel_logout_supported: bool,
+    pub frontchannel_logout_session_supported: bool,
+    pub dpop_signing_alg_values_supported: Vec<String>,
+}
+
+/// Content encryption algorithms for JWEs from and to the provider
+const ENCRYPTION_ENCS: &[&str] = &[
+    "A128CBC-HS256",
+    "A192CBC-HS384",
+    "A256CBC-HS512",
+    "A128GCM",
+    "A192GCM",
+    "A256GCM",
+];
+
+const TOKEN_ENDPOINT_AUTH_METHODS: &[&str] = &[
+    "client_secret_basic",
+    TLS_CLIENT_AUTH,
+    SELF_SIGNED_TLS_CLIENT_AUTH,
+];
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+impl ProviderMetadata {
+    pub fn new(acr_policy: &AcrPolicy) -> Self {
+        Self {
+            issuer: ISSUER.to_string(),
+            authorization_endpoint: format!("{}/authenticate", ISSUER),
+            pushed_authorization_request_endpoint: format!("{}/par", ISSUER),
+            require_pushed_authorization_requests: false,
+            token_endpoint: format!("{}/token", ISSUER),
+            introspection_endpoint: format!("{}/introspect", ISSUER),
+            userinfo_endpoint: format!("{}/userinfo", ISSUER),
+            end_session_endpoint: format!("{}/end_session", ISSUER),
+            check_session_iframe: format!("{}/check_session", ISSUER),
+            jwks_uri: String::from("https://oidc-test-jwks.s3.amazonaws.com/jwks.json"),
+            scopes_supported: strings(&["openid", "profile", "email", "address", "phone"]),
+            acr_values_supported: acr_policy.supported(),
+            claims_parameter_supported: true,
+            request_parameter_supported: true,
+            request_uri_parameter_supported: true,
+            require_request_uri_registration: true,
+            request_object_signing_alg_values_supported: JWS_ALGS
+                .iter()
+                .chain(&["none"])
+                .map(|a| a.to_string())
+                .collect(),
+            request_object_encryption_alg_values_supported: strings(
+                &request_object::ENCRYPTION_ALGS,
+            ),
+            request_object_encryption_enc_values_supported: strings(ENCRYPTION_ENCS),
+            response_types_supported: strings(&[
+                "code",
+                "id_token",
+                "id_token token",
+                "code id_token",
+                "code token",
+                "code id_token token",
+            ]),
+            response_modes_supported: strings(&[
+                "query",
+                "fragment",
+                "form_post",
+                "query.jwt",
+                "fragment.jwt",
+                "form_post.jwt",
+                "jwt",
+            ]),
+            grant_types_supported: strings(&["authorization_code", "implicit"]),
+            subject_types_supported: strings(&["public"]),
+            id_token_signing_alg_values_supported: strings(&["RS256"]),
+            token_endpoint_auth_methods_supported: strings(TOKEN_ENDPOINT_AUTH_METHODS),
+            introspection_endpoint_auth_methods_supported: strings(TOKEN_ENDPOINT_AUTH_METHODS),
+            tls_client_certificate_bound_access_tokens: true,
+            authorization_signing_alg_values_supported: strings(&["RS256"]),
+            authorization_encryption_alg_values_supported: strings(&["RSA-OAEP", "RSA-OAEP-256"]),
+            authorization_encryption_enc_values_supported: strings(ENCRYPTION_ENCS),
+            backchannel_logout_supported: true,
+            backchannel_logout_session_supported: true,
+            frontchannel_logout_supported: true,
+            frontchannel_logout_session_supported: true,
+            dpop_signing_alg_values_supported: strings(&JWS_ALGS),
+        }
+    }
+}
+
+impl Default for ProviderMetadata {
+    fn default() -> Self {
+        Self::new(&AcrPolicy::default())
+    }
+}
@@ -3,7 +3,7 @@ use std::{iter::FromIterator, str::FromStr};
 use anyhow::{anyhow, Result};
 use rocket::form::{self, DataField, Errors, FromFormField, ValueField};
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Scopes {
     pub scopes: Vec<Scope>,
 }
@@ -18,6 +18,13 @@ impl FromStr for Scopes {
     }
 }
 
+impl Scopes {
+    /// Claims released to the client when all the scopes are granted
+    pub fn claims(&self) -> Vec<&'static str> {
+        self.scopes.iter().flat_map(|s| s.claims()).collect()
+    }
+}
+
 impl FromIterator<Scope> for Scopes {
     fn from_iter<T: IntoIterator<Item = Scope>>(iter: T) -> Self {
         let mut scopes = Scopes { scopes: vec![] };
@@ -38,7 +45,7 @@ impl ToString for Scopes {
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Scope {
     OpenID,
     Profile,
@@ -47,6 +54,35 @@ pub enum Scope {
     Email,
 }
 
+impl Scope {
+    /// Human readable description shown on the consent page
+    pub fn description(&self) -> &'static str {
+        match self {
+            Scope::OpenID => "Sign you in with your account",
+            Scope::Profile => "View your basic profile",
+            Scope::Address => "View your postal address",
+            Scope::Phone => "View your phone number",
+            Scope::Email => "View your email address",
+        }
+    }
+
+    /// Claims released to the client when the scope is granted
+    pub fn claims(&self) -> Vec<&'static str> {
+        match self {
+            Scope::OpenID => vec!["sub"],
+            Scope::Profile => vec!["name"],
+            Scope::Address => vec!["address"],
+            Scope::Phone => vec!["phone_number", "phone_number_verified"],
+            Scope::Email => vec!["email", "email_verified"],
+        }
+    }
+
+    /// The end-user can't deselect a required scope on the consent page
+    pub fn is_required(&self) -> bool {
+        self == &Scope::OpenID
+    }
+}
+
 impl FromStr for Scope {
     type Err = anyhow::Error;
 
@@ -99,6 +135,43 @@ impl FromStr for ResponseTypes {
     }
 }
 
+impl ResponseTypes {
+    pub fn contains(&self, res_type: &ResponseType) -> bool {
+        self.types.contains(res_type)
+    }
+
+    /// Whether the combination is one of the response types defined by
+    /// OpenID Connect (authorization code, implicit and hybrid flows)
+    pub fn is_supported(&self) -> bool {
+        let code = self.contains(&ResponseType::Code);
+        let id_token = self.contains(&ResponseType::IdToken);
+        let token = self.contains(&ResponseType::Token);
+        let len = [code, id_token, token].iter().filter(|t| **t).count();
+        len == self.types.len() && (code || id_token)
+    }
+
+    /// Only the authorization code flow uses the query string by default.
+    pub fn default_response_mode(&self) -> ResponseMode {
+        if self.contains(&ResponseType::IdToken) || self.contains(&ResponseType::Token) {
+            ResponseMode::Fragment
+        } else {
+            ResponseMode::Query
+        }
+    }
+
+    /// Every flow but the authorization code flow returns tokens from the
+    /// authorization endpoint and has to bind them to the request with a nonce
+    /// https://openid.net/specs/openid-connect-core-1_0.html#HybridAuthRequest
+    pub fn requires_nonce(&self) -> bool {
+        self.contains(&ResponseType::IdToken) || self.contains(&ResponseType::Token)
+    }
+
+    /// Tokens must never be returned in the query string
+    pub fn allows_response_mode(&self, mode: &ResponseMode) -> bool {
+        !(mode.base() == ResponseMode::Query && self.default_response_mode() != ResponseMode::Query)
+    }
+}
+
 impl FromIterator<ResponseType> for ResponseTypes {
     fn from_iter<T: IntoIterator<Item = ResponseType>>(iter: T) -> Self {
         let mut res_types = ResponseTypes { types: vec![] };
@@ -112,12 +185,16 @@ impl FromIterator<ResponseType> for ResponseTypes {
 #[derive(PartialEq, Debug)]
 pub enum ResponseType {
     Code,
+    IdToken,
+    Token,
 }
 
 impl ToString for ResponseType {
     fn to_string(&self) -> String {
         match self {
             &ResponseType::Code => String::from("code"),
+            &ResponseType::IdToken => String::from("id_token"),
+            &ResponseType::Token => String::from("token"),
         }
     }
 }
@@ -128,11 +205,84 @@ impl FromStr for ResponseType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "code" => Ok(ResponseType::Code),
+            "id_token" => Ok(ResponseType::IdToken),
+            "token" => Ok(ResponseType::Token),
             _ => Err(anyhow!("Unsupported response_type")),
         }
     }
 }
 
+/// ResponseMode represents how authorization response parameters are returned
+/// https://openid.net/specs/oauth-v2-multiple-response-types-1_0.html#ResponseModes
+/// https://openid.net/specs/oauth-v2-form-post-response-mode-1_0.html
+/// https://openid.net/specs/oauth-v2-jarm.html#name-response-mode-jwt
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ResponseMode {
+    Query,
+    Fragment,
+    FormPost,
+    QueryJwt,
+    FragmentJwt,
+    FormPostJwt,
+}
+
+impl ResponseMode {
+    /// Whether the response parameters are wrapped in a JWT (JARM)
+    pub fn is_jwt(&self) -> bool {
+        matches!(
+            self,
+            ResponseMode::QueryJwt | ResponseMode::FragmentJwt | ResponseMode::FormPostJwt
+        )
+    }
+
+    /// The response mode used to transmit the parameters (or the response JWT)
+    pub fn base(&self) -> ResponseMode {
+        match self {
+            ResponseMode::Query | ResponseMode::QueryJwt => ResponseMode::Query,
+            ResponseMode::Fragment | ResponseMode::FragmentJwt => ResponseMode::Fragment,
+            ResponseMode::FormPost | ResponseMode::FormPostJwt => ResponseMode::FormPost,
+        }
+    }
+
+    /// The JARM counterpart of the response mode
+    pub fn jwt(&self) -> ResponseMode {
+        match self.base() {
+            ResponseMode::Query => ResponseMode::QueryJwt,
+            ResponseMode::Fragment => ResponseMode::FragmentJwt,
+            _ => ResponseMode::FormPostJwt,
+        }
+    }
+}
+
+impl ToString for ResponseMode {
+    fn to_string(&self) -> String {
+        match self {
+            &ResponseMode::Query => String::from("query"),
+            &ResponseMode::Fragment => String::from("fragment"),
+            &ResponseMode::FormPost => String::from("form_post"),
+            &ResponseMode::QueryJwt => String::from("query.jwt"),
+            &ResponseMode::FragmentJwt => String::from("fragment.jwt"),
+            &ResponseMode::FormPostJwt => String::from("form_post.jwt"),
+        }
+    }
+}
+
+impl FromStr for ResponseMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "query" => Ok(ResponseMode::Query),
+            "fragment" => Ok(ResponseMode::Fragment),
+            "form_post" => Ok(ResponseMode::FormPost),
+            "query.jwt" => Ok(ResponseMode::QueryJwt),
+            "fragment.jwt" => Ok(ResponseMode::FragmentJwt),
+            "form_post.jwt" => Ok(ResponseMode::FormPostJwt),
+            _ => Err(anyhow!("Unsupported response_mode")),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub enum GrantType {
     AuthorizationCode,
@@ -242,6 +392,92 @@ mod tests {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn response_type_hybrid_from_str_ok() {
+        let result = ResponseTypes::from_str("code id_token token");
+        let expected = ResponseTypes {
+            types: vec![
+                ResponseType::Code,
+                ResponseType::IdToken,
+                ResponseType::Token,
+            ],
+        };
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn response_type_is_supported_ok() {
+        for input in [
+            "code",
+            "id_token",
+            "id_token token",
+            "code id_token",
+            "code token",
+            "code id_token token",
+        ] {
+            assert!(ResponseTypes::from_str(input).unwrap().is_supported());
+        }
+    }
+
+    #[test]
+    fn response_type_is_supported_ng() {
+        for input in ["token", "code code", "id_token id_token token"] {
+            assert!(!ResponseTypes::from_str(input).unwrap().is_supported());
+        }
+    }
+
+    #[test]
+    fn response_type_default_response_mode() {
+        let code = ResponseTypes::from_str("code").unwrap();
+        assert_eq!(ResponseMode::Query, code.default_response_mode());
+        let implicit = ResponseTypes::from_str("id_token").unwrap();
+        assert_eq!(ResponseMode::Fragment, implicit.default_response_mode());
+        let hybrid = ResponseTypes::from_str("code token").unwrap();
+        assert_eq!(ResponseMode::Fragment, hybrid.default_response_mode());
+    }
+
+    #[test]
+    fn response_type_requires_nonce() {
+        assert!(!ResponseTypes::from_str("code").unwrap().requires_nonce());
+        for input in ["id_token", "id_token token", "code id_token", "code token"] {
+            assert!(ResponseTypes::from_str(input).unwrap().requires_nonce());
+        }
+    }
+
+    #[test]
+    fn response_type_allows_response_mode() {
+        let code = ResponseTypes::from_str("code").unwrap();
+        assert!(code.allows_response_mode(&ResponseMode::Query));
+        assert!(code.allows_response_mode(&ResponseMode::FormPost));
+        let hybrid = ResponseTypes::from_str("code id_token").unwrap();
+        assert!(!hybrid.allows_response_mode(&ResponseMode::Query));
+        assert!(hybrid.allows_response_mode(&ResponseMode::Fragment));
+        assert!(hybrid.allows_response_mode(&ResponseMode::FormPost));
+    }
+
+    #[test]
+    fn response_mode_from_str_ok() {
+        let result = ResponseMode::from_str("form_post");
+        assert_eq!(ResponseMode::FormPost, result.unwrap());
+    }
+
+    #[test]
+    fn response_mode_jwt() {
+        assert_eq!(ResponseMode::QueryJwt, ResponseMode::Query.jwt());
+        assert_eq!(ResponseMode::FragmentJwt, ResponseMode::FragmentJwt.jwt());
+        assert_eq!(ResponseMode::FormPost, ResponseMode::FormPostJwt.base());
+        assert!(ResponseMode::from_str("fragment.jwt").unwrap().is_jwt());
+        assert!(!ResponseMode::Fragment.is_jwt());
+        let hybrid = ResponseTypes::from_str("code id_token").unwrap();
+        assert!(!hybrid.allows_response_mode(&ResponseMode::QueryJwt));
+    }
+
+    #[test]
+    fn response_mode_from_str_ng() {
+        let result = ResponseMode::from_str("aaaaa");
+        assert!(result.is_err());
+    }
+
     #[test]
     fn grant_type_from_str_ok() {
         let result = GrantType::from_str("authorization_code");
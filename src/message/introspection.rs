@@ -0,0 +1,37 @@
+use serde::Serialize;
+
+use super::token::Confirmation;
+
+/// IntrospectionRequest represents a token introspection request
+/// https://www.rfc-editor.org/rfc/rfc7662.html#section-2.1
+#[derive(FromForm)]
+pub struct IntrospectionRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+    /// identifies clients authenticating with a certificate
+    pub client_id: Option<String>,
+}
+
+/// IntrospectionResponse represents a token introspection response.
+/// Only `active` is returned for tokens that are unknown or no longer valid.
+/// https://www.rfc-editor.org/rfc/rfc7662.html#section-2.2
+#[derive(Serialize, Default)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    /// the keys the token is bound to, which resource servers have to check
+    /// the presenter possesses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+}
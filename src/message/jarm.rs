@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use josekit::{
+    jwe::{self, JweHeader, RSA_OAEP, RSA_OAEP_256},
+    jwk::JwkSet,
+};
+use serde_json::{Map, Value};
+
+use crate::{models::Client, utils::sign_jwt};
+
+use super::discovery::ISSUER;
+
+/// Jarm wraps authorization response parameters in a JWT signed by the provider
+/// and, when the client registered it, encrypted to the client's key
+/// https://openid.net/specs/oauth-v2-jarm.html
+#[derive(Debug)]
+pub struct Jarm {
+    client_id: String,
+    encryption: Option<JarmEncryption>,
+}
+
+#[derive(Debug)]
+struct JarmEncryption {
+    alg: String,
+    enc: String,
+    jwks: Option<String>,
+}
+
+impl Jarm {
+    pub fn new(client: &Client) -> Self {
+        Self {
+            client_id: client.client_id.clone(),
+            encryption: client
+                .authorization_encrypted_response_alg
+                .as_ref()
+                .map(|alg| JarmEncryption {
+                    alg: alg.clone(),
+                    enc: client
+                        .authorization_encrypted_response_enc
+                        .clone()
+                        .unwrap_or(String::from("A128CBC-HS256")),
+                    jwks: client.jwks.clone(),
+                }),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Builds the value of the `response` parameter
+    pub fn wrap(&self, params: &[(&str, Value)]) -> Result<String> {
+        let now = Utc::now();
+        let exp = now + Duration::minutes(10);
+        let mut claims = Map::new();
+        claims.insert("iss".to_string(), Value::from(ISSUER));
+        claims.insert("aud".to_string(), Value::from(self.client_id.clone()));
+        claims.insert("exp".to_string(), Value::from(exp.timestamp()));
+        for (name, value) in params {
+            claims.insert(name.to_string(), value.clone());
+        }
+        let jwt = sign_jwt(&claims)?;
+        match &self.encryption {
+            Some(encryption) => encryption.encrypt(&jwt),
+            None => Ok(jwt),
+        }
+    }
+}
+
+impl JarmEncryption {
+    fn encrypt(&self, jwt: &str) -> Result<String> {
+        let jwks = self
+            .jwks
+            .as_ref()
+            .ok_or(anyhow!("client has no jwks registered"))?;
+        let jwks = JwkSet::from_bytes(jwks.as_bytes())?;
+        let jwk = jwks
+            .keys()
+            .into_iter()
+            .find(|k| k.key_type() == "RSA" && k.key_use().map(|u| u == "enc").unwrap_or(true))
+            .ok_or(anyhow!("client has no encryption key"))?;
+        let mut header = JweHeader::new();
+        header.set_content_type("JWT");
+        header.set_content_encryption(&self.enc);
+        if let Some(kid) = jwk.key_id() {
+            header.set_key_id(kid);
+        }
+        let token = match self.alg.as_str() {
+            "RSA-OAEP" => {
+                jwe::serialize_compact(jwt.as_bytes(), &header, &RSA_OAEP.encrypter_from_jwk(jwk)?)?
+            }
+            "RSA-OAEP-256" => jwe::serialize_compact(
+                jwt.as_bytes(),
+                &header,
+                &RSA_OAEP_256.encrypter_from_jwk(jwk)?,
+            )?,
+            _ => return Err(anyhow!("unsupported authorization_encrypted_response_alg")),
+        };
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use josekit::jwe::JweDecrypter;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    fn params() -> Vec<(&'static str, Value)> {
+        vec![
+            ("access_token", Value::from("token")),
+            ("expires_in", Value::from(3600)),
+            ("state", Value::from("xyz")),
+        ]
+    }
+
+    /// Verifies the JWT the way the client does, with the provider's public key
+    fn verify(jwt: &str) -> Map<String, Value> {
+        let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
+        validation.iss = Some(ISSUER.to_string());
+        validation.aud = Some(HashSet::from(["client".to_string()]));
+        jsonwebtoken::decode::<Map<String, Value>>(
+            jwt,
+            &jsonwebtoken::DecodingKey::from_rsa_pem(include_bytes!("../public-key.pem")).unwrap(),
+            &validation,
+        )
+        .unwrap()
+        .claims
+    }
+
+    fn assert_claims(claims: &Map<String, Value>) {
+        assert_eq!(json!(ISSUER), claims["iss"]);
+        assert_eq!(json!("client"), claims["aud"]);
+        assert!(claims["exp"].as_i64().unwrap() > Utc::now().timestamp());
+        assert_eq!(json!("token"), claims["access_token"]);
+        assert_eq!(json!(3600), claims["expires_in"]);
+        assert_eq!(json!("xyz"), claims["state"]);
+    }
+
+    #[test]
+    fn wrap_signed_ok() {
+        let jarm = Jarm::new(&Client::for_test());
+        assert!(!jarm.is_encrypted());
+        let jwt = jarm.wrap(&params()).unwrap();
+        assert_claims(&verify(&jwt));
+    }
+
+    #[test]
+    fn wrap_encrypted_ok() {
+        let key_pair = RSA_OAEP_256.generate_key_pair(2048).unwrap();
+        let mut jwk = key_pair.to_jwk_public_key();
+        jwk.set_key_use("enc");
+        jwk.set_key_id("key-1");
+        let jwks = json!({ "keys": [jwk.as_ref()] }).to_string();
+        let jarm = Jarm::new(&Client {
+            jwks: Some(jwks),
+            authorization_encrypted_response_alg: Some(String::from("RSA-OAEP-256")),
+            ..Client::for_test()
+        });
+        assert!(jarm.is_encrypted());
+        let jwe = jarm.wrap(&params()).unwrap();
+        let decrypter = RSA_OAEP_256
+            .decrypter_from_der(key_pair.to_der_private_key())
+            .unwrap();
+        assert_eq!("RSA-OAEP-256", decrypter.algorithm().name());
+        let (jwt, header) = jwe::deserialize_compact(&jwe, &decrypter).unwrap();
+        assert_eq!(Some("A128CBC-HS256"), header.content_encryption());
+        assert_eq!(Some("key-1"), header.key_id());
+        assert_claims(&verify(std::str::from_utf8(&jwt).unwrap()));
+    }
+
+    #[test]
+    fn wrap_encrypted_ng() {
+        // encryption was registered without a key to encrypt to
+        let jarm = Jarm::new(&Client {
+            authorization_encrypted_response_alg: Some(String::from("RSA-OAEP-256")),
+            ..Client::for_test()
+        });
+        assert!(jarm.wrap(&params()).is_err());
+    }
+}
@@ -1,38 +1,164 @@
 use rocket::{
-    http::{hyper::header::LOCATION, Cookie, Header, Status},
-    response::Responder,
+    http::{hyper::header::LOCATION, Cookie, CookieJar, Header, SameSite, Status},
+    response::{Redirect, Responder},
     Response,
 };
-use time::{Duration, OffsetDateTime};
+use time::Duration;
 
 #[derive(FromForm)]
 pub struct LoginParams {
     pub username: String,
     pub password: String,
     pub login_challenge: String,
+    pub csrf_token: String,
     pub state: Option<String>,
 }
 
+/// Second factor entered after the password: a TOTP code or a recovery code
+#[derive(FromForm)]
+pub struct MfaParams {
+    pub code: String,
+    pub login_challenge: String,
+    pub csrf_token: String,
+    pub state: Option<String>,
+}
+
+/// LoginResponse represents the outcome of a successful password login.
+/// Users who enrolled a second factor are sent to the MFA page before getting a session.
+#[derive(Responder)]
+pub enum LoginResponse {
+    LoggedIn(RedirectWithCookie),
+    SecondFactor(Redirect),
+}
+
+/// Builds a session cookie that expires together with the stored session
+pub fn session_cookie(
+    name: String,
+    value: String,
+    max_age: i64,
+    secure: bool,
+    http_only: bool,
+) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path("/")
+        .max_age(Duration::seconds(max_age))
+        .secure(secure)
+        .http_only(http_only)
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+/// Builds the cookie the check_session iframe reads. The iframe is embedded in the
+/// client's pages, so the cookie is sent cross-site, which needs SameSite=None and
+/// with it Secure, even where the session cookie isn't.
+/// https://openid.net/specs/openid-connect-session-1_0.html#OPiframe
+pub fn browser_state_cookie(value: String, max_age: i64) -> Cookie<'static> {
+    Cookie::build("browser_state", value)
+        .path("/")
+        .max_age(Duration::seconds(max_age))
+        .secure(true)
+        .http_only(false)
+        .same_site(SameSite::None)
+        .finish()
+}
+
+/// Builds the cookie holding the browser's CSRF token. It lives as long as the
+/// browser does so that login and consent pages in several tabs keep working.
+pub fn csrf_cookie(value: String, secure: bool) -> Cookie<'static> {
+    Cookie::build("csrf_token", value)
+        .path("/")
+        .secure(secure)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
 pub struct RedirectWithCookie {
-    pub key: String,
+    /// the session id
     pub value: String,
+    /// opaque value the check_session iframe reads to detect session changes
+    pub browser_state: String,
+    /// seconds until the browser drops the cookies
+    pub max_age: i64,
+    pub secure: bool,
     pub next: String,
 }
 
+/// Sets the session cookies, or refreshes their lifetime when the session is used again
+pub fn add_session_cookies(
+    cookies: &CookieJar<'_>,
+    session_id: String,
+    browser_state: String,
+    max_age: i64,
+    secure: bool,
+) {
+    // the session id is encrypted so that it can't be forged or read by scripts
+    cookies.add_private(session_cookie(
+        String::from("session_id"),
+        session_id,
+        max_age,
+        secure,
+        true,
+    ));
+    // must stay readable from JavaScript for the check_session iframe
+    cookies.add(browser_state_cookie(browser_state, max_age));
+}
+
 impl<'r> Responder<'r, 'static> for RedirectWithCookie {
-    fn respond_to(self, _request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-        // TODO: set cookie expiration
-        let _exp = OffsetDateTime::now_utc()
-            .checked_add(Duration::hours(12))
-            .expect("failed to calculate cookie expiration");
-
-        let cookie = Cookie::build(self.key, self.value)
-            // .expires(exp)
-            .finish();
+    fn respond_to(self, request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        add_session_cookies(
+            request.cookies(),
+            self.value,
+            self.browser_state,
+            self.max_age,
+            self.secure,
+        );
         Response::build()
             .status(Status::Found)
-            .header(cookie)
             .header(Header::new(LOCATION.as_str(), self.next))
             .ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{
+        http::Method,
+        local::blocking::Client,
+        route::{self, BoxFuture},
+        Data, Request, Route,
+    };
+
+    /// Logs in without requiring secure cookies, as the debug profile does
+    fn login<'r>(request: &'r Request<'_>, _: Data) -> BoxFuture<'r> {
+        Box::pin(async move {
+            let res = RedirectWithCookie {
+                value: String::from("session"),
+                browser_state: String::from("state"),
+                max_age: 3600,
+                secure: false,
+                next: String::from("/"),
+            };
+            route::Outcome::from(request, res)
+        })
+    }
+
+    #[test]
+    fn session_cookies_ok() {
+        let rocket = rocket::build().mount("/", vec![Route::new(Method::Get, "/", login)]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+        let res = client.get("/").dispatch();
+        assert_eq!(Status::Found, res.status());
+        let session_id = res.cookies().get("session_id").unwrap();
+        assert_eq!(Some(SameSite::Lax), session_id.same_site());
+        assert_eq!(Some(true), session_id.http_only());
+        assert_eq!(None, session_id.secure());
+        let browser_state = res.cookies().get("browser_state").unwrap();
+        assert_eq!("state", browser_state.value());
+        assert_eq!(Some(SameSite::None), browser_state.same_site());
+        assert_eq!(Some(true), browser_state.secure());
+        assert_eq!(None, browser_state.http_only());
+        assert_eq!(Some(Duration::seconds(3600)), browser_state.max_age());
+    }
+}
@@ -0,0 +1,22 @@
+use rocket::response::Redirect;
+use rocket_dyn_templates::Template;
+
+/// EndSessionParams represents an RP-initiated logout request
+/// https://openid.net/specs/openid-connect-rpinitiated-1_0.html#RPLogout
+#[derive(FromForm, Clone)]
+pub struct EndSessionParams {
+    pub id_token_hint: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    pub client_id: Option<String>,
+    pub state: Option<String>,
+    /// sent by the logout page to confirm the logout
+    pub csrf_token: Option<String>,
+}
+
+/// EndSessionResponse represents the outcome of a logout request
+#[derive(Responder)]
+pub enum EndSessionResponse {
+    Confirm(Template),
+    LoggedOut(Template),
+    Redirect(Redirect),
+}
@@ -0,0 +1,43 @@
+use rocket::{http::Status, response::Responder, serde::json::Json, Request};
+use serde::Serialize;
+
+use super::authentication::AuthorizationError;
+
+/// Prefix of the request_uri values handed out by `/par`
+pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// How long a pushed request can be redeemed for, in seconds
+pub const PAR_LIFETIME: i64 = 60;
+
+/// SuccessfulPushedAuthorizationResponse represents a successful pushed authorization response
+/// https://www.rfc-editor.org/rfc/rfc9126.html#section-2.2
+#[derive(Serialize)]
+pub struct SuccessfulPushedAuthorizationResponse {
+    pub request_uri: String,
+    pub expires_in: i64,
+}
+
+/// ErrorPushedAuthorizationResponse represents an error response of `/par`.
+/// Unlike authentication errors, it goes back to the client directly rather
+/// than through a redirect.
+/// https://www.rfc-editor.org/rfc/rfc9126.html#section-2.3
+#[derive(Serialize, Debug)]
+pub struct ErrorPushedAuthorizationResponse {
+    error: String,
+}
+
+impl ErrorPushedAuthorizationResponse {
+    pub fn new(error: &AuthorizationError) -> Self {
+        Self {
+            error: error.to_string(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ErrorPushedAuthorizationResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let mut res = Json(self).respond_to(request)?;
+        res.set_status(Status::BadRequest);
+        Ok(res)
+    }
+}
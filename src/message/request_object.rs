@@ -0,0 +1,268 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use josekit::{
+    jwe::{self, JweDecrypter, RSA_OAEP, RSA_OAEP_256},
+    jwk::JwkSet,
+    jwt::{self, JwtPayload},
+};
+use serde_json::Value;
+
+use crate::{models::Client, utils::jws_verifier};
+
+use super::{authentication::AuthenticationRequestParam, discovery::ISSUER};
+
+/// `alg` values request objects may be encrypted to the provider's key with
+pub const ENCRYPTION_ALGS: [&str; 2] = ["RSA-OAEP", "RSA-OAEP-256"];
+
+/// Request objects fetched from a request_uri larger than this are rejected
+const MAX_REQUEST_OBJECT_SIZE: usize = 64 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Fetches the request object from a request_uri.
+/// Callers have to check the client registered the URI, so that the provider
+/// can't be made to send requests anywhere else.
+pub async fn fetch(uri: &str) -> Result<String> {
+    let res = reqwest::Client::new()
+        .get(uri)
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?;
+    if res
+        .content_length()
+        .filter(|l| *l as usize > MAX_REQUEST_OBJECT_SIZE)
+        .is_some()
+    {
+        return Err(anyhow!("request object is too large"));
+    }
+    let body = res.bytes().await?;
+    if body.len() > MAX_REQUEST_OBJECT_SIZE {
+        return Err(anyhow!("request object is too large"));
+    }
+    Ok(String::from_utf8(body.to_vec())?)
+}
+
+/// Decodes a request object sent by the client, decrypting it when it is a JWE
+/// and verifying its signature with the client's registered keys, into the
+/// authentication request parameters it carries.
+/// https://openid.net/specs/openid-connect-core-1_0.html#JWTRequests
+/// https://www.rfc-editor.org/rfc/rfc9101.html
+pub fn decode(request: &str, client: &Client) -> Result<AuthenticationRequestParam> {
+    let request = request.trim();
+    // a nested JWT encrypted to the provider has five parts
+    let jwt = if request.split('.').count() == 5 {
+        decrypt(request)?
+    } else {
+        request.to_string()
+    };
+    let header = jwt::decode_header(&jwt)?;
+    let alg = header
+        .claim("alg")
+        .and_then(|a| a.as_str())
+        .ok_or(anyhow!("request object has no alg"))?
+        .to_string();
+    if let Some(registered) = &client.request_object_signing_alg {
+        if registered != &alg {
+            return Err(anyhow!("request object is not signed with {}", registered));
+        }
+    }
+    let payload = if alg == "none" {
+        if client.require_signed_request_object {
+            return Err(anyhow!("client requires signed request objects"));
+        }
+        jwt::decode_unsecured(&jwt)?.0
+    } else {
+        let kid = header
+            .claim("kid")
+            .and_then(|k| k.as_str())
+            .map(String::from);
+        let jwks = client
+            .jwks
+            .as_ref()
+            .ok_or(anyhow!("client has no jwks registered"))?;
+        let jwks = JwkSet::from_bytes(jwks.as_bytes())?;
+        let jwk = jwks
+            .keys()
+            .into_iter()
+            .find(|k| match &kid {
+                Some(kid) => k.key_id() == Some(kid.as_str()),
+                None => k.key_use().map(|u| u == "sig").unwrap_or(true),
+            })
+            .ok_or(anyhow!("no key of the client verifies the request object"))?;
+        let verifier = jws_verifier(&alg, jwk)?;
+        jwt::decode_with_verifier(&jwt, verifier.as_ref())?.0
+    };
+    validate(&payload, client)?;
+    Ok(to_param(&payload))
+}
+
+fn decrypt(jwe: &str) -> Result<String> {
+    let header = jwt::decode_header(jwe)?;
+    let private_key = include_bytes!("../private-key.pem");
+    let decrypter: Box<dyn JweDecrypter> = match header.claim("alg").and_then(|a| a.as_str()) {
+        Some("RSA-OAEP") => Box::new(RSA_OAEP.decrypter_from_pem(private_key)?),
+        Some("RSA-OAEP-256") => Box::new(RSA_OAEP_256.decrypter_from_pem(private_key)?),
+        _ => return Err(anyhow!("unsupported request object encryption alg")),
+    };
+    let (payload, _) = jwe::deserialize_compact(jwe, decrypter.as_ref())?;
+    Ok(String::from_utf8(payload)?.trim().to_string())
+}
+
+/// The request object has to come from the client, be meant for this provider
+/// and be within its validity period, for whichever of these claims it has
+fn validate(payload: &JwtPayload, client: &Client) -> Result<()> {
+    if payload
+        .issuer()
+        .filter(|i| i != &client.client_id)
+        .is_some()
+    {
+        return Err(anyhow!("request object is issued by another client"));
+    }
+    if payload
+        .audience()
+        .filter(|aud| !aud.contains(&ISSUER))
+        .is_some()
+    {
+        return Err(anyhow!("request object is meant for another provider"));
+    }
+    if payload
+        .claim("client_id")
+        .filter(|c| c.as_str() != Some(client.client_id.as_str()))
+        .is_some()
+    {
+        return Err(anyhow!("request object is for another client"));
+    }
+    let now = SystemTime::now();
+    if payload.expires_at().filter(|exp| exp <= &now).is_some() {
+        return Err(anyhow!("request object has expired"));
+    }
+    if payload.not_before().filter(|nbf| nbf > &now).is_some() {
+        return Err(anyhow!("request object is not valid yet"));
+    }
+    Ok(())
+}
+
+fn string(payload: &JwtPayload, name: &str) -> Option<String> {
+    payload
+        .claim(name)
+        .and_then(|v| v.as_str())
+        .map(String::from)
+}
+
+fn to_param(payload: &JwtPayload) -> AuthenticationRequestParam {
+    AuthenticationRequestParam {
+        scope: string(payload, "scope"),
+        response_type: string(payload, "response_type"),
+        client_id: string(payload, "client_id"),
+        redirect_uri: string(payload, "redirect_uri"),
+        state: string(payload, "state"),
+        nonce: string(payload, "nonce"),
+        max_age: payload.claim("max_age").and_then(|m| match m {
+            Value::String(s) => s.parse().ok(),
+            m => m.as_u64(),
+        }),
+        response_mode: string(payload, "response_mode"),
+        acr_values: string(payload, "acr_values"),
+        // members are objects here rather than JSON text as in the query
+        claims: payload
+            .claim("claims")
+            .filter(|c| c.is_object())
+            .map(|c| c.to_string()),
+        // a request object must not refer to another one
+        request: None,
+        request_uri: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use josekit::jws::{JwsHeader, RS256};
+    use serde_json::json;
+
+    fn with_jwks(jwks: String) -> Client {
+        Client {
+            jwks: Some(jwks),
+            ..Client::for_test()
+        }
+    }
+
+    fn payload() -> JwtPayload {
+        let mut payload = JwtPayload::new();
+        payload.set_issuer("client");
+        payload.set_audience(vec![ISSUER]);
+        payload.set_expires_at(&(SystemTime::now() + Duration::from_secs(60)));
+        payload
+            .set_claim("client_id", Some(json!("client")))
+            .unwrap();
+        payload.set_claim("scope", Some(json!("openid"))).unwrap();
+        payload.set_claim("max_age", Some(json!(300))).unwrap();
+        payload
+            .set_claim("claims", Some(json!({"id_token": {"acr": null}})))
+            .unwrap();
+        payload
+    }
+
+    /// Signs the payload with a fresh key, returning the JWT and the client's jwks
+    fn sign(payload: &JwtPayload) -> (String, String) {
+        let key_pair = RS256.generate_key_pair(2048).unwrap();
+        let mut jwk = key_pair.to_jwk_public_key();
+        jwk.set_key_id("key-1");
+        let mut header = JwsHeader::new();
+        header.set_key_id("key-1");
+        let signer = RS256
+            .signer_from_der(key_pair.to_der_private_key())
+            .unwrap();
+        let jwt = jwt::encode_with_signer(payload, &header, &signer).unwrap();
+        (jwt, json!({ "keys": [jwk.as_ref()] }).to_string())
+    }
+
+    #[test]
+    fn decode_signed_ok() {
+        let (jwt, jwks) = sign(&payload());
+        let param = decode(&jwt, &with_jwks(jwks)).unwrap();
+        assert_eq!(Some("openid".to_string()), param.scope);
+        assert_eq!(Some(300), param.max_age);
+        assert_eq!(
+            Some(r#"{"id_token":{"acr":null}}"#.to_string()),
+            param.claims
+        );
+        assert_eq!(None, param.request);
+    }
+
+    #[test]
+    fn decode_signed_ng() {
+        let (jwt, _) = sign(&payload());
+        let (_, other_jwks) = sign(&payload());
+        assert!(decode(&jwt, &Client::for_test()).is_err());
+        assert!(decode(&jwt, &with_jwks(other_jwks.clone())).is_err());
+        let mut expired = payload();
+        expired.set_expires_at(&(SystemTime::now() - Duration::from_secs(60)));
+        let (jwt, jwks) = sign(&expired);
+        assert!(decode(&jwt, &with_jwks(jwks)).is_err());
+        let mut other_audience = payload();
+        other_audience.set_audience(vec!["https://other.example.com"]);
+        let (jwt, jwks) = sign(&other_audience);
+        assert!(decode(&jwt, &with_jwks(jwks)).is_err());
+        // registered with another alg
+        let (jwt, jwks) = sign(&payload());
+        let c = Client {
+            request_object_signing_alg: Some("ES256".to_string()),
+            ..with_jwks(jwks)
+        };
+        assert!(decode(&jwt, &c).is_err());
+    }
+
+    #[test]
+    fn decode_unsigned_ok() {
+        let jwt = jwt::encode_unsecured(&payload(), &JwsHeader::new()).unwrap();
+        let param = decode(&jwt, &Client::for_test()).unwrap();
+        assert_eq!(Some("client".to_string()), param.client_id);
+        let c = Client {
+            require_signed_request_object: true,
+            ..Client::for_test()
+        };
+        assert!(decode(&jwt, &c).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 
 use anyhow::Result;
+use openssl::x509::X509;
 use rocket::{
     http::Status,
     request::{self, FromRequest, Outcome},
@@ -8,7 +9,12 @@ use rocket::{
 };
 use serde::{Deserialize, Serialize};
 
-use super::enums::GrantType;
+use crate::{
+    models::Client,
+    mtls::{self, ClientCertificate},
+};
+
+use super::{claims::Claims, enums::GrantType};
 
 pub struct Basic {
     pub client_id: String,
@@ -58,11 +64,80 @@ impl<'r> FromRequest<'r> for Basic {
     }
 }
 
+/// ClientCredentials are what a client authenticates with: its secret in
+/// Basic authentication or its TLS client certificate
+pub struct ClientCredentials {
+    pub basic: Option<Basic>,
+    pub certificate: Option<X509>,
+    /// whether the proxy verified the certificate's chain
+    pub certificate_verified: bool,
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ClientCredentials {
+    type Error = anyhow::Error;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let basic = Basic::from_request(request).await.succeeded();
+        let (certificate, certificate_verified) =
+            match ClientCertificate::from_request(request).await {
+                Outcome::Success(ClientCertificate {
+                    certificate,
+                    verified,
+                }) => (certificate, verified),
+                Outcome::Failure(e) => return Outcome::Failure(e),
+                Outcome::Forward(f) => return Outcome::Forward(f),
+            };
+        if basic.is_none() && certificate.is_none() {
+            return Outcome::Failure((Status::Unauthorized, anyhow::anyhow!("no credentials")));
+        }
+        Outcome::Success(ClientCredentials {
+            basic,
+            certificate,
+            certificate_verified,
+        })
+    }
+}
+
+impl ClientCredentials {
+    /// The client that presented the credentials. Certificates don't name one,
+    /// so the `client_id` parameter does.
+    pub fn client_id<'a>(&'a self, client_id: Option<&'a str>) -> Option<&'a str> {
+        match &self.basic {
+            Some(basic) => Some(&basic.client_id),
+            None => client_id,
+        }
+    }
+
+    /// Whether these are the credentials of the client, by the method it registered
+    pub fn authenticate(&self, client: &Client, client_id: Option<&str>) -> bool {
+        match client.token_endpoint_auth_method.as_str() {
+            mtls::TLS_CLIENT_AUTH | mtls::SELF_SIGNED_TLS_CLIENT_AUTH => {
+                self.client_id(client_id) == Some(client.client_id.as_str())
+                    && self
+                        .certificate
+                        .as_ref()
+                        .filter(|c| mtls::authenticates(c, self.certificate_verified, client))
+                        .is_some()
+            }
+            _ => self
+                .basic
+                .as_ref()
+                .filter(|b| {
+                    b.client_id == client.client_id && b.client_secret == client.client_secret
+                })
+                .is_some(),
+        }
+    }
+}
+
 #[derive(FromForm)]
 pub struct TokenRequest {
     grant_type: GrantType,
     code: String,
     redirect_uri: String,
+    /// identifies clients authenticating with a certificate
+    client_id: Option<String>,
 }
 
 impl TokenRequest {
@@ -77,6 +152,45 @@ impl TokenRequest {
     pub fn redirect_uri(&self) -> &str {
         &self.redirect_uri
     }
+
+    pub fn client_id(&self) -> Option<&str> {
+        self.client_id.as_deref()
+    }
+}
+
+/// Confirmation (`cnf`) names the keys an access token is bound to
+/// https://www.rfc-editor.org/rfc/rfc7800.html
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Confirmation {
+    /// thumbprint of the DPoP key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jkt: Option<String>,
+    /// thumbprint of the client certificate
+    #[serde(default, rename = "x5t#S256", skip_serializing_if = "Option::is_none")]
+    pub x5t_s256: Option<String>,
+}
+
+impl Confirmation {
+    pub fn is_empty(&self) -> bool {
+        self.jkt.is_none() && self.x5t_s256.is_none()
+    }
+}
+
+/// AccessTokenClaims are the claims of a JWT access token (`typ: at+jwt`)
+/// https://www.rfc-editor.org/rfc/rfc9068.html#section-2.2
+#[derive(Serialize, Deserialize)]
+pub struct AccessTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub client_id: String,
+    pub scope: String,
+    /// what the token is stored by, so that it can be revoked and introspected
+    pub jti: String,
+    pub exp: i64,
+    pub iat: i64,
+    #[serde(default, skip_serializing_if = "Confirmation::is_empty")]
+    pub cnf: Confirmation,
 }
 
 #[derive(Serialize)]
@@ -101,6 +215,21 @@ pub struct IdToken {
     pub exp: usize,
     pub iat: usize,
     pub nonce: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub at_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amr: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+    /// claims about the end-user requested through the `claims` parameter
+    #[serde(flatten)]
+    pub claims: Claims,
 }
 
 pub enum TokenError {
@@ -5,8 +5,14 @@ use rocket::{
 };
 use serde::Serialize;
 
+use super::claims::Claims;
+
+/// UserinfoRequest is the access token presented to the userinfo endpoint,
+/// as a bearer token or together with a DPoP proof
 pub struct UserinfoRequest {
     pub bearer: String,
+    /// whether the `DPoP` scheme was used
+    pub dpop: bool,
 }
 
 #[async_trait]
@@ -17,16 +23,20 @@ impl<'r> FromRequest<'r> for UserinfoRequest {
         match request.headers().get_one("Authorization") {
             Some(h) => {
                 let auth_headers = h.split_whitespace().collect::<Vec<&str>>();
-                let prefix = auth_headers.get(0);
-                if prefix.is_none() || prefix.unwrap() != &"Bearer" {
-                    return Outcome::Failure((
-                        Status::Unauthorized,
-                        anyhow::anyhow!("invalid token"),
-                    ));
-                }
+                let dpop = match auth_headers.get(0) {
+                    Some(&"Bearer") => false,
+                    Some(&"DPoP") => true,
+                    _ => {
+                        return Outcome::Failure((
+                            Status::Unauthorized,
+                            anyhow::anyhow!("invalid token"),
+                        ))
+                    }
+                };
                 match auth_headers.get(1) {
                     Some(token) => Outcome::Success(UserinfoRequest {
                         bearer: token.to_string(),
+                        dpop,
                     }),
                     None => {
                         Outcome::Failure((Status::Unauthorized, anyhow::anyhow!("invalid token")))
@@ -48,15 +58,12 @@ pub struct Address {
     pub country: String,
 }
 
+/// Only the claims released to the client are included
 #[derive(Serialize)]
 pub struct SuccessfulUserinfoResponse {
     pub sub: String,
-    pub name: String, // sample data
-    pub email: String,
-    pub email_verified: bool,
-    pub address: Address,
-    pub phone_number: String,
-    pub phone_number_verified: bool,
+    #[serde(flatten)]
+    pub claims: Claims,
 }
 
 #[derive(Serialize)]
@@ -0,0 +1,153 @@
+use serde::Serialize;
+
+use crate::webauthn::{encode, rp_id, COSE_ALG_ES256, COSE_ALG_RS256};
+
+/// milliseconds the browser waits for the authenticator
+const TIMEOUT: u64 = 5 * 60 * 1000;
+
+/// Credential created by `navigator.credentials.create()`, fields base64url encoded
+#[derive(FromForm)]
+pub struct RegisterCredentialParams {
+    pub challenge: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(FromForm)]
+pub struct DeleteCredentialParams {
+    pub credential_id: String,
+    pub csrf_token: String,
+}
+
+/// Assertion made by `navigator.credentials.get()` on the login page,
+/// fields base64url encoded
+#[derive(FromForm)]
+pub struct WebAuthnLoginParams {
+    pub login_challenge: String,
+    pub csrf_token: String,
+    pub state: Option<String>,
+    pub challenge: String,
+    pub credential_id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    /// base64url encoded user handle
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Serialize)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub alg: i128,
+}
+
+#[derive(Serialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    /// base64url encoded
+    pub id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub user_verification: String,
+}
+
+/// PublicKeyCredentialCreationOptions, with binary values base64url encoded
+/// https://www.w3.org/TR/webauthn-2/#dictdef-publickeycredentialcreationoptions
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingParty,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: u64,
+    pub attestation: String,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+}
+
+fn public_key(id: &str) -> CredentialDescriptor {
+    CredentialDescriptor {
+        credential_type: String::from("public-key"),
+        id: id.to_string(),
+    }
+}
+
+impl CreationOptions {
+    /// Asks for a discoverable, user-verifying credential without attestation.
+    /// `registered` keeps an authenticator from registering twice.
+    pub fn new(challenge: &str, user_id: &str, registered: Vec<String>) -> Self {
+        CreationOptions {
+            challenge: challenge.to_string(),
+            rp: RelyingParty {
+                id: rp_id(),
+                name: String::from("oidc-rs"),
+            },
+            user: UserEntity {
+                id: encode(user_id.as_bytes()),
+                name: user_id.to_string(),
+                display_name: user_id.to_string(),
+            },
+            pub_key_cred_params: [COSE_ALG_ES256, COSE_ALG_RS256]
+                .iter()
+                .map(|alg| CredentialParameters {
+                    credential_type: String::from("public-key"),
+                    alg: *alg,
+                })
+                .collect(),
+            timeout: TIMEOUT,
+            attestation: String::from("none"),
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: String::from("required"),
+                user_verification: String::from("required"),
+            },
+            exclude_credentials: registered.iter().map(|id| public_key(id)).collect(),
+        }
+    }
+}
+
+/// PublicKeyCredentialRequestOptions, with binary values base64url encoded.
+/// Credentials are discoverable, so the browser offers every passkey it has
+/// for the relying party.
+/// https://www.w3.org/TR/webauthn-2/#dictdef-publickeycredentialrequestoptions
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: u64,
+    pub user_verification: String,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+}
+
+impl RequestOptions {
+    pub fn new(challenge: &str) -> Self {
+        RequestOptions {
+            challenge: challenge.to_string(),
+            rp_id: rp_id(),
+            timeout: TIMEOUT,
+            user_verification: String::from("required"),
+            allow_credentials: vec![],
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use chrono::Utc;
+use crypto::{digest::Digest, hmac::Hmac, mac::Mac, sha1::Sha1, sha2::Sha256};
+use diesel::{result::Error::NotFound, MysqlConnection, QueryResult};
+use rand::{Rng, RngCore};
+
+use crate::{redirect::RedirectBuilder, repository};
+
+/// Authentication method reference values (RFC 8176)
+pub const AMR_PASSWORD: &str = "pwd";
+pub const AMR_OTP: &str = "otp";
+/// passkey kept on the authenticator
+pub const AMR_HARDWARE_KEY: &str = "hwk";
+/// passkey that may be synced between devices
+pub const AMR_SOFTWARE_KEY: &str = "swk";
+
+/// Shown as the account's issuer in authenticator apps
+const TOTP_ISSUER: &str = "oidc-rs";
+const TOTP_DIGITS: u32 = 6;
+const TOTP_PERIOD: i64 = 30;
+/// accepted clock drift between the server and the authenticator, in time steps
+const TOTP_SKEW: i64 = 1;
+const SECRET_LENGTH: usize = 20;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Base32 without padding (RFC 4648), the encoding authenticator apps expect
+fn base32_encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = vec![];
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Generates a new random TOTP secret, base32 encoded
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// The `otpauth://` URI authenticator apps enroll the secret from, usually via a QR code
+/// https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+pub fn provisioning_uri(secret: &str, account: &str) -> String {
+    let label: String = form_urlencoded::byte_serialize(account.as_bytes()).collect();
+    RedirectBuilder::new(&format!("otpauth://totp/{}:{}", TOTP_ISSUER, label))
+        .param("secret", secret)
+        .param("issuer", TOTP_ISSUER)
+        .param("algorithm", "SHA1")
+        .param("digits", &TOTP_DIGITS.to_string())
+        .param("period", &TOTP_PERIOD.to_string())
+        .query()
+}
+
+/// HOTP value for the counter (RFC 4226)
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::new(Sha1::new(), secret);
+    mac.input(&counter.to_be_bytes());
+    let result = mac.result();
+    let hash = result.code();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+/// Checks a TOTP code (RFC 6238) at unix time `now`. Returns the time step the
+/// code belongs to, which has to be later than `last_used_step` so that a code
+/// can't be used twice.
+pub fn verify_totp(secret: &str, code: &str, now: i64, last_used_step: Option<i64>) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let secret = base32_decode(secret)?;
+    let current = now / TOTP_PERIOD;
+    (current - TOTP_SKEW..=current + TOTP_SKEW)
+        .filter(|step| *step > last_used_step.unwrap_or(i64::MIN))
+        .find(|step| hotp(&secret, *step as u64) == code)
+}
+
+/// Generates the recovery codes shown to the user once after enrolling
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| {
+                    RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char
+                })
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect()
+}
+
+/// Hashes a recovery code for storage, ignoring case and separators
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let mut hash_sha256 = Sha256::new();
+    hash_sha256.input_str(&normalized);
+    hash_sha256.result_str()
+}
+
+/// Whether logging in as the user requires a second factor
+pub fn is_enrolled(user_id: &str, conn: &MysqlConnection) -> QueryResult<bool> {
+    match repository::find_totp_credential(user_id, conn) {
+        Ok(credential) => Ok(credential.confirmed),
+        Err(NotFound) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks the code entered on the MFA page against the user's TOTP secret,
+/// falling back to the unused recovery codes
+pub fn verify_second_factor(
+    user_id: &str,
+    code: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<bool> {
+    let credential = repository::find_totp_credential(user_id, conn)?;
+    if let Some(step) = verify_totp(
+        &credential.secret,
+        code,
+        Utc::now().timestamp(),
+        credential.last_used_step,
+    ) {
+        return Ok(repository::use_totp_step(user_id, step, conn)? == 1);
+    }
+    Ok(repository::delete_recovery_code(user_id, &hash_recovery_code(code), conn)? == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the SHA-1 secret of the RFC 6238 test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn base32_ok() {
+        assert_eq!("MZXW6YTBOI", base32_encode(b"foobar"));
+        assert_eq!(Some(b"foobar".to_vec()), base32_decode("MZXW6YTBOI"));
+        assert_eq!(Some(b"foobar".to_vec()), base32_decode("mzxw6ytboi======"));
+        assert_eq!(None, base32_decode("MZXW6YTBO1"));
+    }
+
+    #[test]
+    fn verify_totp_ok() {
+        let secret = base32_encode(RFC_SECRET);
+        // RFC 6238 Appendix B, truncated to 6 digits
+        assert_eq!(Some(1), verify_totp(&secret, "287082", 59, None));
+        assert_eq!(
+            Some(37037036),
+            verify_totp(&secret, "081804", 1111111109, None)
+        );
+        // the previous time step is still accepted
+        assert_eq!(Some(1), verify_totp(&secret, "287082", 89, None));
+    }
+
+    #[test]
+    fn verify_totp_ng() {
+        let secret = base32_encode(RFC_SECRET);
+        assert_eq!(None, verify_totp(&secret, "287083", 59, None));
+        assert_eq!(None, verify_totp(&secret, "28708", 59, None));
+        // too old
+        assert_eq!(None, verify_totp(&secret, "287082", 120, None));
+        // already used
+        assert_eq!(None, verify_totp(&secret, "287082", 59, Some(1)));
+    }
+
+    #[test]
+    fn provisioning_uri_ok() {
+        assert_eq!(
+            "otpauth://totp/oidc-rs:userid?secret=MZXW6YTBOI&issuer=oidc-rs&algorithm=SHA1&digits=6&period=30",
+            provisioning_uri("MZXW6YTBOI", "userid")
+        );
+    }
+
+    #[test]
+    fn recovery_codes_ok() {
+        let codes = generate_recovery_codes();
+        assert_eq!(RECOVERY_CODE_COUNT, codes.len());
+        assert_eq!(11, codes[0].len());
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&codes[0].to_uppercase().replace('-', " "))
+        );
+        assert_ne!(hash_recovery_code(&codes[0]), hash_recovery_code(&codes[1]));
+    }
+}
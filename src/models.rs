@@ -3,8 +3,10 @@ use std::{convert::TryInto, str::FromStr};
 use crate::{
     error::CustomError,
     message::{
-        authentication::AuthenticationRequest,
+        authentication::{AuthenticationRequest, AuthenticationRequestParam},
+        claims::ClaimsRequest,
         enums::{ResponseTypes, Scopes},
+        token::Confirmation,
     },
     schema::*,
 };
@@ -21,6 +23,34 @@ pub struct Client {
     pub scope: String,
     pub response_type: String,
     pub redirect_uri: String,
+    pub require_auth_time: bool,
+    pub jwks: Option<String>,
+    pub authorization_encrypted_response_alg: Option<String>,
+    pub authorization_encrypted_response_enc: Option<String>,
+    pub post_logout_redirect_uris: Option<String>,
+    pub backchannel_logout_uri: Option<String>,
+    pub backchannel_logout_session_required: bool,
+    pub frontchannel_logout_uri: Option<String>,
+    pub frontchannel_logout_session_required: bool,
+    /// the only `alg` accepted for request objects, when registered
+    pub request_object_signing_alg: Option<String>,
+    /// rejects authentication requests not made with a signed request object
+    pub require_signed_request_object: bool,
+    /// space-separated URIs request objects may be fetched from
+    pub request_uris: Option<String>,
+    /// rejects authentication requests whose parameters weren't pushed to `/par` first
+    pub require_pushed_authorization_requests: bool,
+    /// `client_secret_basic`, `tls_client_auth` or `self_signed_tls_client_auth`
+    pub token_endpoint_auth_method: String,
+    /// the certificate subject a `tls_client_auth` client authenticates with,
+    /// as an RFC 4514 string, or else its DNS name
+    pub tls_client_auth_subject_dn: Option<String>,
+    pub tls_client_auth_san_dns: Option<String>,
+    pub tls_client_certificate_bound_access_tokens: bool,
+    /// issue access tokens as signed JWTs rather than opaque strings
+    pub access_token_jwt: bool,
+    /// the `aud` of JWT access tokens, the userinfo endpoint when not registered
+    pub access_token_audience: Option<String>,
 }
 
 impl Client {
@@ -43,6 +73,67 @@ impl Client {
         }
         Ok(())
     }
+
+    /// Authorization responses are only ever sent to a redirect_uri the client
+    /// registered, compared as exact strings
+    pub fn check_redirect_uri(&self, uri: &str) -> anyhow::Result<()> {
+        if self.redirect_uri.split_whitespace().any(|r| r == uri) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("invalid redirect_uri"))
+        }
+    }
+
+    pub fn check_post_logout_redirect_uri(&self, uri: &str) -> anyhow::Result<()> {
+        let registered = self.post_logout_redirect_uris.as_deref().unwrap_or("");
+        if registered.split_whitespace().any(|r| r == uri) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("invalid post_logout_redirect_uri"))
+        }
+    }
+
+    pub fn check_request_uri(&self, uri: &str) -> anyhow::Result<()> {
+        let registered = self.request_uris.as_deref().unwrap_or("");
+        if registered.split_whitespace().any(|r| r == uri) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("invalid request_uri"))
+        }
+    }
+}
+
+#[cfg(test)]
+impl Client {
+    /// A code flow client with nothing else registered; tests override the fields they need
+    pub fn for_test() -> Self {
+        Client {
+            client_id: String::from("client"),
+            client_secret: String::from("secret"),
+            scope: String::from("openid"),
+            response_type: String::from("code"),
+            redirect_uri: String::from("https://client.example.com/cb"),
+            require_auth_time: false,
+            jwks: None,
+            authorization_encrypted_response_alg: None,
+            authorization_encrypted_response_enc: None,
+            post_logout_redirect_uris: None,
+            backchannel_logout_uri: None,
+            backchannel_logout_session_required: false,
+            frontchannel_logout_uri: None,
+            frontchannel_logout_session_required: false,
+            request_object_signing_alg: None,
+            require_signed_request_object: false,
+            request_uris: None,
+            require_pushed_authorization_requests: false,
+            token_endpoint_auth_method: String::from("client_secret_basic"),
+            tls_client_auth_subject_dn: None,
+            tls_client_auth_san_dns: None,
+            tls_client_certificate_bound_access_tokens: false,
+            access_token_jwt: false,
+            access_token_audience: None,
+        }
+    }
 }
 
 #[derive(Queryable, Insertable, AsChangeset, Serialize, Deserialize)]
@@ -55,10 +146,38 @@ pub struct AuthChallenge {
     pub redirect_uri: String,
     pub state: Option<String>,
     pub nonce: Option<String>,
+    pub user_id: Option<String>,
+    pub auth_time: Option<chrono::NaiveDateTime>,
+    pub max_age: Option<u64>,
+    pub response_mode: String,
+    pub sid: Option<String>,
+    /// also kept in a cookie so that the challenge can only be answered from the same browser
+    pub csrf_token: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub consumed: bool,
+    /// copied from the session, see `Session::amr`
+    pub amr: Option<String>,
+    pub acr: Option<String>,
+    /// space-separated `acr` values the client asked for
+    pub acr_values: Option<String>,
+    pub acr_essential: bool,
+    /// the `claims` parameter as JSON
+    pub claims: Option<String>,
+}
+
+/// Reads back a `claims` parameter stored as JSON
+fn claims_request(claims: &Option<String>) -> Option<ClaimsRequest> {
+    claims
+        .as_deref()
+        .and_then(|c| ClaimsRequest::from_str(c).ok())
 }
 
 impl AuthChallenge {
-    pub fn from_auth_request(challenge: &str, req: AuthenticationRequest) -> Self {
+    pub fn from_auth_request(
+        challenge: &str,
+        csrf_token: &str,
+        req: AuthenticationRequest,
+    ) -> Self {
         AuthChallenge {
             challenge: challenge.to_string(),
             client_id: req.client_id().to_string(),
@@ -67,22 +186,73 @@ impl AuthChallenge {
             redirect_uri: req.redirect_uri().to_string(),
             state: req.state().to_owned(),
             nonce: req.nonce().to_owned(),
+            user_id: None,
+            auth_time: None,
+            max_age: req.max_age().to_owned(),
+            response_mode: req.response_mode().to_string(),
+            sid: None,
+            csrf_token: csrf_token.to_string(),
+            created_at: Utc::now().naive_utc(),
+            consumed: false,
+            amr: None,
+            acr: None,
+            acr_values: Some(req.acr_values().join(" ")).filter(|v| !v.is_empty()),
+            acr_essential: req.acr_essential(),
+            claims: req.claims().as_ref().map(|c| c.to_string()),
         }
     }
+
+    /// Whether the challenge is older than `lifetime` seconds
+    pub fn is_expired(&self, lifetime: i64) -> bool {
+        Utc::now().naive_utc() - self.created_at >= Duration::seconds(lifetime)
+    }
+
+    /// Whether a session was started for the challenge, which ends its login phase:
+    /// it can then only be consented to, not logged in with again
+    pub fn is_logged_in(&self) -> bool {
+        self.sid.is_some()
+    }
+
+    pub fn requested_acr(&self) -> Vec<&str> {
+        self.acr_values
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .collect()
+    }
+
+    /// The authentication methods already verified for the challenge
+    pub fn amr(&self) -> Vec<&str> {
+        self.amr
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .collect()
+    }
+
+    pub fn claims_request(&self) -> Option<ClaimsRequest> {
+        claims_request(&self.claims)
+    }
 }
 
 impl TryInto<AuthenticationRequest> for AuthChallenge {
     type Error = CustomError;
 
     fn try_into(self) -> Result<AuthenticationRequest, Self::Error> {
-        AuthenticationRequest::new(
-            &self.scope,
-            &self.response_type,
-            &self.client_id,
-            &self.redirect_uri,
-            &self.state,
-            &self.nonce,
-        )
+        AuthenticationRequest::new(AuthenticationRequestParam {
+            scope: Some(self.scope),
+            response_type: Some(self.response_type),
+            client_id: Some(self.client_id),
+            redirect_uri: Some(self.redirect_uri),
+            state: self.state,
+            nonce: self.nonce,
+            max_age: self.max_age,
+            response_mode: Some(self.response_mode),
+            acr_values: None,
+            claims: None,
+            request: None,
+            request_uri: None,
+        })
     }
 }
 
@@ -90,6 +260,51 @@ impl TryInto<AuthenticationRequest> for AuthChallenge {
 #[table_name = "session"]
 pub struct Session {
     pub session_id: String,
+    pub user_id: String,
+    pub auth_time: chrono::NaiveDateTime,
+    /// Session identifier shared with relying parties; unlike session_id it isn't a secret
+    pub sid: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub last_seen_at: chrono::NaiveDateTime,
+    /// space-separated authentication methods used to log in, e.g. `pwd otp`
+    pub amr: String,
+    /// authentication context class the methods satisfy
+    pub acr: String,
+    /// token the account forms submit to prove they were loaded in this session
+    pub csrf_token: String,
+}
+
+impl Session {
+    /// Whether the end-user authenticated recently enough to satisfy `max_age`
+    pub fn satisfies_max_age(&self, max_age: Option<u64>) -> bool {
+        match max_age {
+            Some(max_age) => {
+                let elapsed = Utc::now().naive_utc() - self.auth_time;
+                // a max_age too large for i64 can't have passed yet
+                elapsed.num_seconds() <= max_age.try_into().unwrap_or(i64::MAX)
+            }
+            None => true,
+        }
+    }
+
+    /// Whether the session outlived its absolute lifetime or was idle for too long
+    pub fn is_expired(&self, idle_timeout: i64) -> bool {
+        let now = Utc::now().naive_utc();
+        now >= self.expires_at || now - self.last_seen_at >= Duration::seconds(idle_timeout)
+    }
+
+    /// Seconds the session cookies should live for: the idle timeout,
+    /// but never past the session's absolute lifetime
+    pub fn cookie_max_age(&self, idle_timeout: i64) -> i64 {
+        let remaining = (self.expires_at - Utc::now().naive_utc()).num_seconds();
+        idle_timeout.min(remaining).max(0)
+    }
+
+    /// Whether a form was submitted with this session's CSRF token.
+    /// Sessions created before the token was introduced have none and accept no form.
+    pub fn has_csrf_token(&self, csrf_token: &str) -> bool {
+        !self.csrf_token.is_empty() && self.csrf_token == csrf_token
+    }
 }
 
 #[derive(Queryable, Insertable, AsChangeset, Serialize, Deserialize)]
@@ -100,22 +315,62 @@ pub struct AuthCode {
     pub user_id: String,
     pub scope: String,
     pub nonce: String,
+    pub auth_time: chrono::NaiveDateTime,
+    pub max_age: Option<u64>,
+    pub sid: Option<String>,
+    pub amr: Option<String>,
+    pub acr: Option<String>,
+    pub claims: Option<String>,
+}
+
+impl AuthCode {
+    pub fn claims_request(&self) -> Option<ClaimsRequest> {
+        claims_request(&self.claims)
+    }
 }
 
 #[derive(Queryable)]
 pub struct Token {
+    /// the opaque token, or the `jti` of a JWT access token
     pub access_token: String,
     pub user_id: String,
     pub scope: String,
     pub created_at: chrono::NaiveDateTime,
+    pub client_id: String,
+    /// the `claims` parameter of the authentication request, for the userinfo member
+    pub claims: Option<String>,
+    /// thumbprint of the DPoP key the token is bound to; such a token isn't a bearer token
+    pub dpop_jkt: Option<String>,
+    /// thumbprint of the client certificate the token is bound to
+    pub x5t_s256: Option<String>,
 }
 
 impl Token {
-    pub fn is_valid(&self) -> bool {
+    pub fn claims_request(&self) -> Option<ClaimsRequest> {
+        claims_request(&self.claims)
+    }
+
+    pub fn expires_at(&self) -> chrono::NaiveDateTime {
         // Expiration: 1 hour
-        let expired_at = self.created_at + Duration::hours(1);
-        let now = Utc::now().naive_utc();
-        expired_at >= now
+        self.created_at + Duration::hours(1)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.expires_at() >= Utc::now().naive_utc()
+    }
+
+    pub fn cnf(&self) -> Confirmation {
+        Confirmation {
+            jkt: self.dpop_jkt.clone(),
+            x5t_s256: self.x5t_s256.clone(),
+        }
+    }
+
+    /// Whether the token is presented with proof of possession of the keys it is bound to.
+    /// Only a DPoP-bound token may be presented with a DPoP proof.
+    pub fn is_bound_to(&self, presented: &Confirmation) -> bool {
+        self.dpop_jkt == presented.jkt
+            && (self.x5t_s256.is_none() || self.x5t_s256 == presented.x5t_s256)
     }
 }
 
@@ -125,11 +380,203 @@ pub struct NewToken {
     pub access_token: String,
     pub user_id: String,
     pub scope: String,
+    pub client_id: String,
+    pub claims: Option<String>,
+    pub dpop_jkt: Option<String>,
+    pub x5t_s256: Option<String>,
+}
+
+#[derive(Queryable)]
+pub struct Grant {
+    pub user_id: String,
+    pub client_id: String,
+    pub scope: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl Grant {
+    /// Whether the user has already consented to every requested scope
+    pub fn covers(&self, scopes: &Scopes) -> bool {
+        match Scopes::from_str(&self.scope) {
+            Ok(granted) => scopes.scopes.iter().all(|s| granted.scopes.contains(s)),
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "grants"]
+pub struct NewGrant {
+    pub user_id: String,
+    pub client_id: String,
+    pub scope: String,
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "session_clients"]
+pub struct SessionClient {
+    pub sid: String,
+    pub client_id: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "logout_deliveries"]
+pub struct NewLogoutDelivery {
+    pub sid: String,
+    pub client_id: String,
+    pub uri: String,
+    pub attempt: i32,
+    pub status: Option<i32>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+/// Failed login attempts counted per account or per client IP address
+#[derive(Queryable, Insertable, AsChangeset)]
+#[table_name = "login_failures"]
+pub struct LoginFailure {
+    /// `account` or `ip`
+    pub scope: String,
+    pub subject: String,
+    /// consecutive failures since the last lockout
+    pub failures: i32,
+    pub last_failed_at: chrono::NaiveDateTime,
+    pub locked_until: Option<chrono::NaiveDateTime>,
+}
+
+impl LoginFailure {
+    pub fn new(scope: &str, subject: &str) -> Self {
+        LoginFailure {
+            scope: scope.to_string(),
+            subject: subject.to_string(),
+            failures: 0,
+            last_failed_at: Utc::now().naive_utc(),
+            locked_until: None,
+        }
+    }
+
+    /// When the next attempt is allowed, if not yet: after a lockout ends or after
+    /// the back-off, which doubles with every consecutive failure up to `max_backoff`
+    pub fn blocked_until(
+        &self,
+        backoff_base: i64,
+        max_backoff: i64,
+    ) -> Option<chrono::NaiveDateTime> {
+        let backoff = if self.failures > 0 {
+            let factor = 1i64 << (self.failures - 1).min(30);
+            let seconds = backoff_base.saturating_mul(factor).min(max_backoff);
+            Some(self.last_failed_at + Duration::seconds(seconds))
+        } else {
+            None
+        };
+        let now = Utc::now().naive_utc();
+        vec![self.locked_until, backoff]
+            .into_iter()
+            .flatten()
+            .filter(|t| *t > now)
+            .max()
+    }
+
+    /// Counts a failed attempt and locks out once `threshold` is reached.
+    /// Returns whether this attempt triggered a lockout.
+    pub fn record_failure(&mut self, threshold: i32, lockout_duration: i64) -> bool {
+        let now = Utc::now().naive_utc();
+        // failures that are older than a lockout no longer count
+        if self.last_failed_at + Duration::seconds(lockout_duration) < now {
+            self.failures = 0;
+        }
+        self.failures += 1;
+        self.last_failed_at = now;
+        if self.failures < threshold {
+            return false;
+        }
+        self.failures = 0;
+        self.locked_until = Some(now + Duration::seconds(lockout_duration));
+        true
+    }
+}
+
+/// TOTP secret a user enrolled as second factor.
+/// It only protects logins once the user proved it works with a valid code.
+#[derive(Queryable, Insertable, AsChangeset)]
+#[table_name = "totp_credentials"]
+pub struct TotpCredential {
+    pub user_id: String,
+    /// base32 encoded, as shown to the user
+    pub secret: String,
+    pub confirmed: bool,
+    /// time step of the last accepted code so that it can't be replayed
+    pub last_used_step: Option<i64>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Single-use code that replaces a TOTP code when the authenticator is lost.
+/// Only a hash of the code is stored.
+#[derive(Queryable, Insertable)]
+#[table_name = "recovery_codes"]
+pub struct RecoveryCode {
+    pub user_id: String,
+    pub code_hash: String,
+}
+
+/// A passkey registered for passwordless login
+#[derive(Queryable, Insertable)]
+#[table_name = "webauthn_credentials"]
+pub struct WebAuthnCredential {
+    /// base64url, as the browser reports it
+    pub credential_id: String,
+    pub user_id: String,
+    /// base64url encoded COSE_Key
+    pub public_key: String,
+    pub sign_count: i64,
+    pub backup_eligible: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Challenge of a WebAuthn ceremony in progress: registration by a logged-in
+/// user or an assertion answering a login challenge
+#[derive(Queryable, Insertable)]
+#[table_name = "webauthn_challenges"]
+pub struct WebAuthnChallenge {
+    /// base64url, as sent to the browser
+    pub challenge: String,
+    pub user_id: Option<String>,
+    pub login_challenge: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl WebAuthnChallenge {
+    /// Whether the challenge is older than `lifetime` seconds
+    pub fn is_expired(&self, lifetime: i64) -> bool {
+        Utc::now().naive_utc() - self.created_at >= Duration::seconds(lifetime)
+    }
+}
+
+/// Authentication request parameters a client pushed to `/par`,
+/// redeemed once through the `request_uri` it was given
+#[derive(Queryable, Insertable)]
+#[table_name = "pushed_authorization_requests"]
+pub struct PushedAuthorizationRequest {
+    pub request_uri: String,
+    pub client_id: String,
+    /// the parameters as JSON, see `AuthenticationRequestParam`
+    pub params: String,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+impl PushedAuthorizationRequest {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::message::enums::{ResponseType, Scope};
+    use crate::message::{
+        enums::{ResponseType, Scope},
+        par::PAR_LIFETIME,
+    };
 
     use super::*;
 
@@ -139,11 +586,8 @@ mod tests {
             scopes: vec![Scope::OpenID, Scope::Profile],
         };
         let client = Client {
-            client_id: String::default(),
-            client_secret: String::default(),
             scope: String::from("openid email profile"),
-            response_type: String::default(),
-            redirect_uri: String::default(),
+            ..Client::for_test()
         };
         assert!(client.check_scopes(&input).is_ok());
     }
@@ -154,11 +598,8 @@ mod tests {
             scopes: vec![Scope::OpenID, Scope::Email],
         };
         let client = Client {
-            client_id: String::default(),
-            client_secret: String::default(),
             scope: String::from("openid profile"),
-            response_type: String::default(),
-            redirect_uri: String::default(),
+            ..Client::for_test()
         };
         assert!(client.check_scopes(&input).is_err());
     }
@@ -169,13 +610,298 @@ mod tests {
             types: vec![ResponseType::Code],
         };
         let client = Client {
+            response_type: String::from("code"),
+            ..Client::for_test()
+        };
+        assert!(client.check_restypes(&input).is_ok());
+    }
+
+    #[test]
+    fn client_check_redirect_uri_ok() {
+        let client = Client::for_test();
+        assert!(client
+            .check_redirect_uri("https://client.example.com/cb")
+            .is_ok());
+    }
+
+    #[test]
+    fn client_check_redirect_uri_ng() {
+        let client = Client::for_test();
+        for uri in [
+            "",
+            "https://evil.example.com/cb",
+            "https://client.example.com/cb/",
+            "https://client.example.com/cb?next=https://evil.example.com",
+            "https://client.example.com",
+        ] {
+            assert!(client.check_redirect_uri(uri).is_err());
+        }
+    }
+
+    #[test]
+    fn client_check_post_logout_redirect_uri_ok() {
+        let client = Client {
+            post_logout_redirect_uris: Some(String::from(
+                "https://rp.example.com/logout https://rp.example.com/bye",
+            )),
+            ..Client::for_test()
+        };
+        assert!(client
+            .check_post_logout_redirect_uri("https://rp.example.com/bye")
+            .is_ok());
+    }
+
+    #[test]
+    fn client_check_post_logout_redirect_uri_ng() {
+        let client = Client {
+            post_logout_redirect_uris: Some(String::from("https://rp.example.com/logout")),
+            ..Client::for_test()
+        };
+        assert!(client
+            .check_post_logout_redirect_uri("https://evil.example.com/logout")
+            .is_err());
+    }
+
+    #[test]
+    fn grant_covers_ok() {
+        let input = Scopes {
+            scopes: vec![Scope::OpenID, Scope::Email],
+        };
+        let grant = Grant {
+            user_id: String::default(),
+            client_id: String::default(),
+            scope: String::from("openid profile email"),
+            created_at: Utc::now().naive_utc(),
+        };
+        assert!(grant.covers(&input));
+    }
+
+    #[test]
+    fn grant_covers_ng() {
+        let input = Scopes {
+            scopes: vec![Scope::OpenID, Scope::Phone],
+        };
+        let grant = Grant {
+            user_id: String::default(),
+            client_id: String::default(),
+            scope: String::from("openid profile"),
+            created_at: Utc::now().naive_utc(),
+        };
+        assert!(!grant.covers(&input));
+    }
+
+    #[test]
+    fn session_satisfies_max_age_ok() {
+        let session = Session {
+            session_id: String::default(),
+            user_id: String::default(),
+            auth_time: Utc::now().naive_utc() - Duration::seconds(30),
+            sid: String::default(),
+            expires_at: Utc::now().naive_utc(),
+            last_seen_at: Utc::now().naive_utc(),
+            amr: String::from("pwd"),
+            acr: String::default(),
+            csrf_token: String::default(),
+        };
+        assert!(session.satisfies_max_age(None));
+        assert!(session.satisfies_max_age(Some(60)));
+        assert!(session.satisfies_max_age(Some(u64::MAX)));
+    }
+
+    #[test]
+    fn session_satisfies_max_age_ng() {
+        let session = Session {
+            session_id: String::default(),
+            user_id: String::default(),
+            auth_time: Utc::now().naive_utc() - Duration::seconds(120),
+            sid: String::default(),
+            expires_at: Utc::now().naive_utc(),
+            last_seen_at: Utc::now().naive_utc(),
+            amr: String::from("pwd"),
+            acr: String::default(),
+            csrf_token: String::default(),
+        };
+        assert!(!session.satisfies_max_age(Some(60)));
+    }
+
+    fn auth_challenge(created_at: chrono::NaiveDateTime) -> AuthChallenge {
+        AuthChallenge {
+            challenge: String::default(),
             client_id: String::default(),
-            client_secret: String::default(),
-            scope: String::default(),
+            scope: String::from("openid"),
             response_type: String::from("code"),
             redirect_uri: String::default(),
+            state: None,
+            nonce: None,
+            user_id: None,
+            auth_time: None,
+            max_age: None,
+            response_mode: String::from("query"),
+            sid: None,
+            csrf_token: String::default(),
+            created_at,
+            consumed: false,
+            amr: None,
+            acr: None,
+            acr_values: None,
+            acr_essential: false,
+            claims: None,
+        }
+    }
+
+    #[test]
+    fn auth_challenge_requested_acr_ok() {
+        let mut challenge = auth_challenge(Utc::now().naive_utc());
+        assert!(challenge.requested_acr().is_empty());
+        challenge.acr_values = Some(String::from("urn:a  urn:b"));
+        assert_eq!(vec!["urn:a", "urn:b"], challenge.requested_acr());
+    }
+
+    #[test]
+    fn auth_challenge_is_expired_ok() {
+        let challenge = auth_challenge(Utc::now().naive_utc() - Duration::seconds(30));
+        assert!(!challenge.is_expired(60));
+    }
+
+    #[test]
+    fn auth_challenge_is_expired_ng() {
+        let challenge = auth_challenge(Utc::now().naive_utc() - Duration::seconds(120));
+        assert!(challenge.is_expired(60));
+    }
+
+    #[test]
+    fn auth_challenge_is_logged_in_ok() {
+        let mut challenge = auth_challenge(Utc::now().naive_utc());
+        assert!(!challenge.is_logged_in());
+        // a password alone doesn't end the login phase while MFA is pending
+        challenge.user_id = Some(String::from("userid"));
+        assert!(!challenge.is_logged_in());
+        challenge.sid = Some(String::from("sid"));
+        assert!(challenge.is_logged_in());
+    }
+
+    #[test]
+    fn login_failure_blocked_until_ok() {
+        let failure = LoginFailure::new("account", "foobar");
+        assert!(failure.blocked_until(1, 900).is_none());
+        let failure = LoginFailure {
+            failures: 3,
+            last_failed_at: Utc::now().naive_utc() - Duration::seconds(5),
+            ..LoginFailure::new("account", "foobar")
         };
-        assert!(client.check_restypes(&input).is_ok());
+        // the third failure in a row waits 4 seconds
+        assert!(failure.blocked_until(1, 900).is_none());
+    }
+
+    #[test]
+    fn login_failure_blocked_until_ng() {
+        let failure = LoginFailure {
+            failures: 4,
+            last_failed_at: Utc::now().naive_utc() - Duration::seconds(5),
+            ..LoginFailure::new("account", "foobar")
+        };
+        // the fourth failure in a row waits 8 seconds
+        assert!(failure.blocked_until(1, 900).is_some());
+        let locked = LoginFailure {
+            locked_until: Some(Utc::now().naive_utc() + Duration::minutes(10)),
+            ..LoginFailure::new("account", "foobar")
+        };
+        assert!(locked.blocked_until(1, 900).is_some());
+    }
+
+    #[test]
+    fn login_failure_record_failure_locks_out() {
+        let mut failure = LoginFailure::new("ip", "127.0.0.1");
+        assert!(!failure.record_failure(3, 900));
+        assert!(!failure.record_failure(3, 900));
+        assert!(failure.record_failure(3, 900));
+        assert_eq!(0, failure.failures);
+        assert!(failure.locked_until.is_some());
+    }
+
+    #[test]
+    fn session_is_expired_ok() {
+        let session = Session {
+            session_id: String::default(),
+            user_id: String::default(),
+            auth_time: Utc::now().naive_utc(),
+            sid: String::default(),
+            expires_at: Utc::now().naive_utc() + Duration::hours(1),
+            last_seen_at: Utc::now().naive_utc() - Duration::seconds(30),
+            amr: String::from("pwd"),
+            acr: String::default(),
+            csrf_token: String::default(),
+        };
+        assert!(!session.is_expired(60));
+    }
+
+    #[test]
+    fn session_is_expired_ng() {
+        let idle = Session {
+            session_id: String::default(),
+            user_id: String::default(),
+            auth_time: Utc::now().naive_utc(),
+            sid: String::default(),
+            expires_at: Utc::now().naive_utc() + Duration::hours(1),
+            last_seen_at: Utc::now().naive_utc() - Duration::seconds(120),
+            amr: String::from("pwd"),
+            acr: String::default(),
+            csrf_token: String::default(),
+        };
+        assert!(idle.is_expired(60));
+        let past_lifetime = Session {
+            session_id: String::default(),
+            user_id: String::default(),
+            auth_time: Utc::now().naive_utc() - Duration::hours(2),
+            sid: String::default(),
+            expires_at: Utc::now().naive_utc() - Duration::seconds(1),
+            last_seen_at: Utc::now().naive_utc(),
+            amr: String::from("pwd"),
+            acr: String::default(),
+            csrf_token: String::default(),
+        };
+        assert!(past_lifetime.is_expired(60));
+    }
+
+    #[test]
+    fn session_cookie_max_age_ok() {
+        let mut session = Session {
+            session_id: String::default(),
+            user_id: String::default(),
+            auth_time: Utc::now().naive_utc(),
+            sid: String::default(),
+            expires_at: Utc::now().naive_utc() + Duration::hours(1),
+            last_seen_at: Utc::now().naive_utc(),
+            amr: String::from("pwd"),
+            acr: String::default(),
+            csrf_token: String::default(),
+        };
+        assert_eq!(1800, session.cookie_max_age(1800));
+        // capped by the absolute lifetime
+        session.expires_at = Utc::now().naive_utc() + Duration::seconds(600);
+        assert!((599..=600).contains(&session.cookie_max_age(1800)));
+        session.expires_at = Utc::now().naive_utc() - Duration::seconds(1);
+        assert_eq!(0, session.cookie_max_age(1800));
+    }
+
+    #[test]
+    fn session_has_csrf_token_ok() {
+        let mut session = Session {
+            session_id: String::default(),
+            user_id: String::default(),
+            auth_time: Utc::now().naive_utc(),
+            sid: String::default(),
+            expires_at: Utc::now().naive_utc() + Duration::hours(1),
+            last_seen_at: Utc::now().naive_utc(),
+            amr: String::from("pwd"),
+            acr: String::default(),
+            csrf_token: String::from("token"),
+        };
+        assert!(session.has_csrf_token("token"));
+        assert!(!session.has_csrf_token("other"));
+        assert!(!session.has_csrf_token(""));
+        session.csrf_token = String::default();
+        assert!(!session.has_csrf_token(""));
     }
 
     #[test]
@@ -184,12 +910,60 @@ mod tests {
             types: vec![ResponseType::Code],
         };
         let client = Client {
-            client_id: String::default(),
-            client_secret: String::default(),
-            scope: String::default(),
             response_type: String::default(),
-            redirect_uri: String::default(),
+            ..Client::for_test()
         };
         assert!(client.check_restypes(&input).is_err());
     }
+
+    fn token(dpop_jkt: Option<&str>, x5t_s256: Option<&str>) -> Token {
+        Token {
+            access_token: String::from("token"),
+            user_id: String::from("user"),
+            scope: String::from("openid"),
+            created_at: Utc::now().naive_utc(),
+            client_id: String::from("client"),
+            claims: None,
+            dpop_jkt: dpop_jkt.map(String::from),
+            x5t_s256: x5t_s256.map(String::from),
+        }
+    }
+
+    fn presented(jkt: Option<&str>, x5t_s256: Option<&str>) -> Confirmation {
+        Confirmation {
+            jkt: jkt.map(String::from),
+            x5t_s256: x5t_s256.map(String::from),
+        }
+    }
+
+    #[test]
+    fn token_is_bound_to_ok() {
+        assert!(token(None, None).is_bound_to(&presented(None, None)));
+        // a certificate may come with a bearer token
+        assert!(token(None, None).is_bound_to(&presented(None, Some("cert"))));
+        assert!(token(Some("key"), None).is_bound_to(&presented(Some("key"), None)));
+        assert!(token(None, Some("cert")).is_bound_to(&presented(None, Some("cert"))));
+    }
+
+    #[test]
+    fn token_is_bound_to_ng() {
+        assert!(!token(Some("key"), None).is_bound_to(&presented(None, None)));
+        assert!(!token(Some("key"), None).is_bound_to(&presented(Some("other"), None)));
+        assert!(!token(None, None).is_bound_to(&presented(Some("key"), None)));
+        assert!(!token(None, Some("cert")).is_bound_to(&presented(None, None)));
+        assert!(!token(None, Some("cert")).is_bound_to(&presented(None, Some("other"))));
+    }
+
+    #[test]
+    fn pushed_authorization_request_is_expired() {
+        let pushed = |expires_at| PushedAuthorizationRequest {
+            request_uri: String::default(),
+            client_id: String::default(),
+            params: String::default(),
+            expires_at,
+        };
+        let now = Utc::now().naive_utc();
+        assert!(!pushed(now + Duration::seconds(PAR_LIFETIME)).is_expired());
+        assert!(pushed(now - Duration::seconds(1)).is_expired());
+    }
 }
@@ -0,0 +1,228 @@
+use openssl::x509::X509;
+use rocket::{
+    http::{RawStr, Status},
+    request::{self, FromRequest, Outcome},
+    Request,
+};
+use serde_json::Value;
+
+use crate::{config::MtlsConfig, models::Client, utils::sha256_base64url};
+
+pub const TLS_CLIENT_AUTH: &str = "tls_client_auth";
+pub const SELF_SIGNED_TLS_CLIENT_AUTH: &str = "self_signed_tls_client_auth";
+
+/// The value of the verification header when the proxy chained the certificate
+/// to a CA it trusts, as nginx's `$ssl_client_verify`
+const VERIFY_SUCCESS: &str = "SUCCESS";
+
+/// ClientCertificate is the certificate the client presented in the TLS handshake,
+/// as forwarded by the trusted reverse proxy; see MtlsConfig for why it can't be
+/// read from Rocket's own TLS connection
+/// https://www.rfc-editor.org/rfc/rfc8705.html
+pub struct ClientCertificate {
+    pub certificate: Option<X509>,
+    /// whether the proxy verified the certificate's chain
+    pub verified: bool,
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for ClientCertificate {
+    type Error = anyhow::Error;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = request.rocket().state::<MtlsConfig>();
+        let header = config
+            .and_then(|c| c.mtls_client_cert_header.as_ref())
+            .and_then(|h| request.headers().get_one(h));
+        let verified = config
+            .and_then(|c| c.mtls_client_verify_header.as_ref())
+            .and_then(|h| request.headers().get_one(h))
+            == Some(VERIFY_SUCCESS);
+        match header.map(parse).transpose() {
+            Ok(certificate) => Outcome::Success(ClientCertificate {
+                certificate,
+                verified,
+            }),
+            Err(e) => Outcome::Failure((Status::BadRequest, e)),
+        }
+    }
+}
+
+/// Reads a certificate forwarded as URL-encoded or plain PEM
+fn parse(header: &str) -> anyhow::Result<X509> {
+    let pem = RawStr::new(header).percent_decode_lossy();
+    Ok(X509::from_pem(pem.trim().as_bytes())?)
+}
+
+/// The certificate's SHA-256 thumbprint (`x5t#S256`) access tokens are bound to
+pub fn thumbprint(cert: &X509) -> String {
+    sha256_base64url(&cert.to_der().unwrap_or_default())
+}
+
+/// The subject DN as an RFC 4514 string, the most specific attribute first
+fn subject_dn(cert: &X509) -> String {
+    let mut rdns: Vec<String> = cert
+        .subject_name()
+        .entries()
+        .filter_map(|e| {
+            let name = e.object().nid().short_name().ok()?;
+            let value = e.data().to_string().ok()?;
+            Some(format!("{}={}", name, value))
+        })
+        .collect();
+    rdns.reverse();
+    rdns.join(",")
+}
+
+fn has_san_dns(cert: &X509, dns: &str) -> bool {
+    cert.subject_alt_names()
+        .map(|names| names.iter().any(|n| n.dnsname() == Some(dns)))
+        .unwrap_or(false)
+}
+
+/// The first certificate of each key's `x5c` in the client's jwks.
+/// Read without josekit, which expects base64url rather than base64 there.
+fn registered_certificates(jwks: &str) -> Vec<Vec<u8>> {
+    let jwks: Value = match serde_json::from_str(jwks) {
+        Ok(jwks) => jwks,
+        Err(_) => return vec![],
+    };
+    jwks["keys"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|k| k["x5c"][0].as_str())
+        .filter_map(|c| base64::decode(c).ok())
+        .collect()
+}
+
+/// Whether the certificate is one the client registered: issued by a trusted CA
+/// to the subject it registered for `tls_client_auth`, or one of the certificates
+/// in its jwks for `self_signed_tls_client_auth`.
+/// Only the proxy checks the chain, so `verified` says whether it did; anyone can
+/// self-sign a certificate with the subject of a `tls_client_auth` client.
+/// The proxy is also trusted with checking the client's possession of the key.
+pub fn authenticates(cert: &X509, verified: bool, client: &Client) -> bool {
+    match client.token_endpoint_auth_method.as_str() {
+        TLS_CLIENT_AUTH if !verified => false,
+        TLS_CLIENT_AUTH => match (
+            &client.tls_client_auth_subject_dn,
+            &client.tls_client_auth_san_dns,
+        ) {
+            (Some(dn), _) => subject_dn(cert).eq_ignore_ascii_case(dn),
+            (None, Some(dns)) => has_san_dns(cert, dns),
+            (None, None) => false,
+        },
+        SELF_SIGNED_TLS_CLIENT_AUTH => {
+            let der = match cert.to_der() {
+                Ok(der) => der,
+                Err(_) => return false,
+            };
+            client
+                .jwks
+                .as_ref()
+                .map(|jwks| registered_certificates(jwks).contains(&der))
+                .unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkey::PKey,
+        rsa::Rsa,
+        x509::{extension::SubjectAlternativeName, X509NameBuilder},
+    };
+    use serde_json::json;
+
+    fn certificate() -> X509 {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("C", "JP").unwrap();
+        name.append_entry_by_text("O", "Example").unwrap();
+        name.append_entry_by_text("CN", "client.example.com")
+            .unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let san = SubjectAlternativeName::new()
+            .dns("client.example.com")
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    #[test]
+    fn parse_ok() {
+        let cert = certificate();
+        let pem = String::from_utf8(cert.to_pem().unwrap()).unwrap();
+        let escaped = pem.replace('\n', "%0A").replace(' ', "%20");
+        let parsed = parse(&escaped).unwrap();
+        assert_eq!(thumbprint(&cert), thumbprint(&parsed));
+        assert!(parse("garbage").is_err());
+    }
+
+    #[test]
+    fn tls_client_auth_ok() {
+        let cert = certificate();
+        assert_eq!("CN=client.example.com,O=Example,C=JP", subject_dn(&cert));
+        let mut c = Client {
+            token_endpoint_auth_method: TLS_CLIENT_AUTH.to_string(),
+            ..Client::for_test()
+        };
+        assert!(!authenticates(&cert, true, &c));
+        c.tls_client_auth_subject_dn = Some("CN=client.example.com,O=Example,C=JP".to_string());
+        assert!(authenticates(&cert, true, &c));
+        c.tls_client_auth_subject_dn = Some("CN=other.example.com,O=Example,C=JP".to_string());
+        assert!(!authenticates(&cert, true, &c));
+        c.tls_client_auth_subject_dn = None;
+        c.tls_client_auth_san_dns = Some("client.example.com".to_string());
+        assert!(authenticates(&cert, true, &c));
+        // a secret-based client can't authenticate with a certificate
+        assert!(!authenticates(&cert, true, &Client::for_test()));
+    }
+
+    #[test]
+    fn tls_client_auth_ng() {
+        // self-signed with the subject the client registered, so the proxy
+        // couldn't chain it to a CA
+        let cert = certificate();
+        let c = Client {
+            token_endpoint_auth_method: TLS_CLIENT_AUTH.to_string(),
+            tls_client_auth_subject_dn: Some("CN=client.example.com,O=Example,C=JP".to_string()),
+            tls_client_auth_san_dns: Some("client.example.com".to_string()),
+            ..Client::for_test()
+        };
+        assert!(!authenticates(&cert, false, &c));
+    }
+
+    #[test]
+    fn self_signed_tls_client_auth_ok() {
+        let cert = certificate();
+        let x5c = base64::encode(cert.to_der().unwrap());
+        let c = Client {
+            token_endpoint_auth_method: SELF_SIGNED_TLS_CLIENT_AUTH.to_string(),
+            jwks: Some(json!({"keys": [{"kty": "RSA", "x5c": [x5c]}]}).to_string()),
+            ..Client::for_test()
+        };
+        // registered certificates need no CA
+        assert!(authenticates(&cert, false, &c));
+        assert!(!authenticates(&certificate(), false, &c));
+    }
+}
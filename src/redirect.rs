@@ -0,0 +1,114 @@
+/// RedirectBuilder builds redirect URIs with percent-encoded parameters.
+/// Parameters are merged into a query string the URI already has.
+pub struct RedirectBuilder {
+    uri: String,
+    params: Vec<(String, String)>,
+}
+
+impl RedirectBuilder {
+    pub fn new(uri: &str) -> Self {
+        Self {
+            uri: uri.to_string(),
+            params: vec![],
+        }
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> Self {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn param_opt(self, name: &str, value: &Option<String>) -> Self {
+        match value {
+            Some(v) => self.param(name, v),
+            None => self,
+        }
+    }
+
+    fn encoded_params(&self) -> String {
+        form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(self.params.iter())
+            .finish()
+    }
+
+    /// Builds the URI with the parameters in the query component
+    pub fn query(&self) -> String {
+        // the fragment, if any, has to stay after the query component
+        let (base, fragment) = match self.uri.find('#') {
+            Some(i) => self.uri.split_at(i),
+            None => (self.uri.as_str(), ""),
+        };
+        if self.params.is_empty() {
+            return self.uri.clone();
+        }
+        let delimiter = match base.find('?') {
+            Some(_) if base.ends_with('?') || base.ends_with('&') => "",
+            Some(_) => "&",
+            None => "?",
+        };
+        format!("{}{}{}{}", base, delimiter, self.encoded_params(), fragment)
+    }
+
+    /// Builds the URI with the parameters in the fragment component
+    pub fn fragment(&self) -> String {
+        let base = match self.uri.find('#') {
+            Some(i) => &self.uri[..i],
+            None => self.uri.as_str(),
+        };
+        format!("{}#{}", base, self.encoded_params())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_ok() {
+        let result = RedirectBuilder::new("https://rp.example.com/cb")
+            .param("code", "abc")
+            .param("state", "a b&c#d")
+            .query();
+        assert_eq!(
+            "https://rp.example.com/cb?code=abc&state=a+b%26c%23d",
+            result
+        );
+    }
+
+    #[test]
+    fn query_merges_existing_query() {
+        let result = RedirectBuilder::new("https://rp.example.com/cb?tenant=1")
+            .param("code", "abc")
+            .query();
+        assert_eq!("https://rp.example.com/cb?tenant=1&code=abc", result);
+    }
+
+    #[test]
+    fn query_keeps_fragment_last() {
+        let result = RedirectBuilder::new("/authorization?x=1#top")
+            .param("consent_challenge", "abc")
+            .query();
+        assert_eq!("/authorization?x=1&consent_challenge=abc#top", result);
+    }
+
+    #[test]
+    fn query_skips_missing_params() {
+        let result = RedirectBuilder::new("https://rp.example.com/cb")
+            .param("error", "access_denied")
+            .param_opt("state", &None)
+            .query();
+        assert_eq!("https://rp.example.com/cb?error=access_denied", result);
+    }
+
+    #[test]
+    fn fragment_ok() {
+        let result = RedirectBuilder::new("https://rp.example.com/cb?tenant=1")
+            .param("id_token", "a.b.c")
+            .param_opt("state", &Some(String::from("x=y")))
+            .fragment();
+        assert_eq!(
+            "https://rp.example.com/cb?tenant=1#id_token=a.b.c&state=x%3Dy",
+            result
+        );
+    }
+}
@@ -1,7 +1,11 @@
-use diesel::QueryDsl;
 use diesel::{query_dsl::RunQueryDsl, MysqlConnection, QueryResult};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl};
 
-use crate::models::{AuthChallenge, AuthCode, Client, NewToken, Session, Token};
+use crate::models::{
+    AuthChallenge, AuthCode, Client, Grant, LoginFailure, NewGrant, NewLogoutDelivery, NewToken,
+    PushedAuthorizationRequest, RecoveryCode, Session, SessionClient, Token, TotpCredential,
+    WebAuthnChallenge, WebAuthnCredential,
+};
 use crate::schema::*;
 
 pub fn create_client(new_client: Client, conn: &MysqlConnection) -> QueryResult<usize> {
@@ -27,6 +31,58 @@ pub fn find_auth_challenge(challenge: &str, conn: &MysqlConnection) -> QueryResu
     auth_challenges::table.find(challenge).first(conn)
 }
 
+/// `acr` is the value asserted to the client, which may be a weaker level
+/// than the session's when the client asked for that one
+pub fn update_auth_challenge_session(
+    challenge: &str,
+    session: &Session,
+    acr: Option<&str>,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    // returns 0 when a session was already started for the challenge
+    diesel::update(
+        auth_challenges::table
+            .find(challenge)
+            .filter(auth_challenges::sid.is_null()),
+    )
+    .set((
+        auth_challenges::user_id.eq(&session.user_id),
+        auth_challenges::auth_time.eq(session.auth_time),
+        auth_challenges::sid.eq(&session.sid),
+        auth_challenges::amr.eq(&session.amr),
+        auth_challenges::acr.eq(acr),
+    ))
+    .execute(conn)
+}
+
+/// Records who authenticated with `amr` while the second factor is pending.
+/// The challenge has no session yet, so it can't be consented to.
+pub fn update_auth_challenge_user(
+    challenge: &str,
+    user_id: &str,
+    amr: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::update(auth_challenges::table.find(challenge))
+        .set((
+            auth_challenges::user_id.eq(user_id),
+            auth_challenges::amr.eq(amr),
+        ))
+        .execute(conn)
+}
+
+/// Marks the challenge as answered. Returns 0 when it already was,
+/// so that two concurrent requests can't both use it.
+pub fn consume_auth_challenge(challenge: &str, conn: &MysqlConnection) -> QueryResult<usize> {
+    diesel::update(
+        auth_challenges::table
+            .find(challenge)
+            .filter(auth_challenges::consumed.eq(false)),
+    )
+    .set(auth_challenges::consumed.eq(true))
+    .execute(conn)
+}
+
 pub fn delete_auth_challenge(challenge: String, conn: &MysqlConnection) -> QueryResult<usize> {
     diesel::delete(auth_challenges::table.find(challenge)).execute(conn)
 }
@@ -41,10 +97,27 @@ pub fn find_session(session_id: &str, conn: &MysqlConnection) -> QueryResult<Ses
     session::table.find(session_id).first(conn)
 }
 
+pub fn touch_session(
+    session_id: &str,
+    last_seen_at: chrono::NaiveDateTime,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::update(session::table.find(session_id))
+        .set(session::last_seen_at.eq(last_seen_at))
+        .execute(conn)
+}
+
 pub fn delete_session(session_id: String, conn: &MysqlConnection) -> QueryResult<usize> {
     diesel::delete(session::table.find(session_id)).execute(conn)
 }
 
+pub fn find_sessions_by_user(user_id: &str, conn: &MysqlConnection) -> QueryResult<Vec<Session>> {
+    session::table
+        .filter(session::user_id.eq(user_id))
+        .order(session::auth_time.desc())
+        .load(conn)
+}
+
 pub fn create_auth_code(new_auth_code: AuthCode, conn: &MysqlConnection) -> QueryResult<usize> {
     diesel::insert_into(auth_code::table)
         .values(&new_auth_code)
@@ -59,6 +132,19 @@ pub fn delete_auth_code(code: String, conn: &MysqlConnection) -> QueryResult<usi
     diesel::delete(auth_code::table.find(code)).execute(conn)
 }
 
+pub fn delete_auth_codes_by_client(
+    user_id: &str,
+    client_id: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::delete(
+        auth_code::table
+            .filter(auth_code::user_id.eq(user_id))
+            .filter(auth_code::client_id.eq(client_id)),
+    )
+    .execute(conn)
+}
+
 pub fn create_token(new_token: NewToken, conn: &MysqlConnection) -> QueryResult<usize> {
     diesel::insert_into(tokens::table)
         .values(&new_token)
@@ -72,3 +158,248 @@ pub fn find_token(token: &str, conn: &MysqlConnection) -> QueryResult<Token> {
 pub fn delete_token(auth_code: String, conn: &MysqlConnection) -> QueryResult<usize> {
     diesel::delete(tokens::table.find(auth_code)).execute(conn)
 }
+
+pub fn delete_tokens_by_client(
+    user_id: &str,
+    client_id: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::delete(
+        tokens::table
+            .filter(tokens::user_id.eq(user_id))
+            .filter(tokens::client_id.eq(client_id)),
+    )
+    .execute(conn)
+}
+
+pub fn save_grant(new_grant: NewGrant, conn: &MysqlConnection) -> QueryResult<usize> {
+    diesel::replace_into(grants::table)
+        .values(&new_grant)
+        .execute(conn)
+}
+
+pub fn find_grant(user_id: &str, client_id: &str, conn: &MysqlConnection) -> QueryResult<Grant> {
+    grants::table.find((user_id, client_id)).first(conn)
+}
+
+pub fn find_grants_by_user(user_id: &str, conn: &MysqlConnection) -> QueryResult<Vec<Grant>> {
+    grants::table
+        .filter(grants::user_id.eq(user_id))
+        .order(grants::created_at.desc())
+        .load(conn)
+}
+
+pub fn delete_grant(user_id: &str, client_id: &str, conn: &MysqlConnection) -> QueryResult<usize> {
+    diesel::delete(grants::table.find((user_id, client_id))).execute(conn)
+}
+
+pub fn save_session_client(
+    session_client: SessionClient,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::replace_into(session_clients::table)
+        .values(&session_client)
+        .execute(conn)
+}
+
+pub fn find_session_clients(sid: &str, conn: &MysqlConnection) -> QueryResult<Vec<SessionClient>> {
+    session_clients::table
+        .filter(session_clients::sid.eq(sid))
+        .load(conn)
+}
+
+pub fn delete_session_clients(sid: &str, conn: &MysqlConnection) -> QueryResult<usize> {
+    diesel::delete(session_clients::table.filter(session_clients::sid.eq(sid))).execute(conn)
+}
+
+pub fn create_logout_delivery(
+    delivery: NewLogoutDelivery,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::insert_into(logout_deliveries::table)
+        .values(&delivery)
+        .execute(conn)
+}
+
+pub fn find_login_failure(
+    scope: &str,
+    subject: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<LoginFailure> {
+    login_failures::table.find((scope, subject)).first(conn)
+}
+
+pub fn save_login_failure(failure: LoginFailure, conn: &MysqlConnection) -> QueryResult<usize> {
+    diesel::replace_into(login_failures::table)
+        .values(&failure)
+        .execute(conn)
+}
+
+pub fn delete_login_failure(
+    scope: &str,
+    subject: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::delete(login_failures::table.find((scope, subject))).execute(conn)
+}
+
+pub fn find_totp_credential(user_id: &str, conn: &MysqlConnection) -> QueryResult<TotpCredential> {
+    totp_credentials::table.find(user_id).first(conn)
+}
+
+pub fn save_totp_credential(
+    credential: TotpCredential,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::replace_into(totp_credentials::table)
+        .values(&credential)
+        .execute(conn)
+}
+
+/// Records the time step of an accepted code. Returns 0 when the step was
+/// already used, so that two concurrent logins can't share a code.
+pub fn use_totp_step(user_id: &str, step: i64, conn: &MysqlConnection) -> QueryResult<usize> {
+    diesel::update(
+        totp_credentials::table.find(user_id).filter(
+            totp_credentials::last_used_step
+                .is_null()
+                .or(totp_credentials::last_used_step.lt(step)),
+        ),
+    )
+    .set(totp_credentials::last_used_step.eq(step))
+    .execute(conn)
+}
+
+/// Replaces the user's recovery codes
+pub fn save_recovery_codes(
+    user_id: &str,
+    codes: Vec<RecoveryCode>,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+        .execute(conn)?;
+    diesel::insert_into(recovery_codes::table)
+        .values(&codes)
+        .execute(conn)
+}
+
+/// Uses up a recovery code. Returns 0 when it doesn't exist or was already used.
+pub fn delete_recovery_code(
+    user_id: &str,
+    code_hash: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::delete(recovery_codes::table.find((user_id, code_hash))).execute(conn)
+}
+
+pub fn count_recovery_codes(user_id: &str, conn: &MysqlConnection) -> QueryResult<i64> {
+    recovery_codes::table
+        .filter(recovery_codes::user_id.eq(user_id))
+        .count()
+        .get_result(conn)
+}
+
+pub fn create_webauthn_challenge(
+    challenge: WebAuthnChallenge,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::insert_into(webauthn_challenges::table)
+        .values(&challenge)
+        .execute(conn)
+}
+
+pub fn find_webauthn_challenge(
+    challenge: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<WebAuthnChallenge> {
+    webauthn_challenges::table.find(challenge).first(conn)
+}
+
+/// Returns 0 when the challenge was already used
+pub fn delete_webauthn_challenge(challenge: &str, conn: &MysqlConnection) -> QueryResult<usize> {
+    diesel::delete(webauthn_challenges::table.find(challenge)).execute(conn)
+}
+
+pub fn create_webauthn_credential(
+    credential: WebAuthnCredential,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::insert_into(webauthn_credentials::table)
+        .values(&credential)
+        .execute(conn)
+}
+
+pub fn find_webauthn_credential(
+    credential_id: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<WebAuthnCredential> {
+    webauthn_credentials::table.find(credential_id).first(conn)
+}
+
+pub fn find_webauthn_credentials_by_user(
+    user_id: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<Vec<WebAuthnCredential>> {
+    webauthn_credentials::table
+        .filter(webauthn_credentials::user_id.eq(user_id))
+        .load(conn)
+}
+
+/// Stores the signature counter of a login. Returns 0 when another login
+/// with the same counter got there first.
+pub fn update_webauthn_sign_count(
+    credential_id: &str,
+    previous: i64,
+    sign_count: i64,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::update(
+        webauthn_credentials::table
+            .find(credential_id)
+            .filter(webauthn_credentials::sign_count.eq(previous)),
+    )
+    .set((
+        webauthn_credentials::sign_count.eq(sign_count),
+        webauthn_credentials::last_used_at.eq(chrono::Utc::now().naive_utc()),
+    ))
+    .execute(conn)
+}
+
+pub fn delete_webauthn_credential(
+    user_id: &str,
+    credential_id: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::delete(
+        webauthn_credentials::table
+            .find(credential_id)
+            .filter(webauthn_credentials::user_id.eq(user_id)),
+    )
+    .execute(conn)
+}
+
+pub fn create_pushed_authorization_request(
+    request: PushedAuthorizationRequest,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::insert_into(pushed_authorization_requests::table)
+        .values(&request)
+        .execute(conn)
+}
+
+pub fn find_pushed_authorization_request(
+    request_uri: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<PushedAuthorizationRequest> {
+    pushed_authorization_requests::table
+        .find(request_uri)
+        .first(conn)
+}
+
+/// Returns 0 when the request_uri was already used
+pub fn delete_pushed_authorization_request(
+    request_uri: &str,
+    conn: &MysqlConnection,
+) -> QueryResult<usize> {
+    diesel::delete(pushed_authorization_requests::table.find(request_uri)).execute(conn)
+}
@@ -7,6 +7,19 @@ table! {
         redirect_uri -> Varchar,
         state -> Nullable<Varchar>,
         nonce -> Nullable<Varchar>,
+        user_id -> Nullable<Varchar>,
+        auth_time -> Nullable<Datetime>,
+        max_age -> Nullable<Unsigned<Bigint>>,
+        response_mode -> Varchar,
+        sid -> Nullable<Varchar>,
+        csrf_token -> Varchar,
+        created_at -> Datetime,
+        consumed -> Bool,
+        amr -> Nullable<Varchar>,
+        acr -> Nullable<Varchar>,
+        acr_values -> Nullable<Varchar>,
+        acr_essential -> Bool,
+        claims -> Nullable<Text>,
     }
 }
 
@@ -17,6 +30,12 @@ table! {
         user_id -> Varchar,
         scope -> Varchar,
         nonce -> Varchar,
+        auth_time -> Datetime,
+        max_age -> Nullable<Unsigned<Bigint>>,
+        sid -> Nullable<Varchar>,
+        amr -> Nullable<Varchar>,
+        acr -> Nullable<Varchar>,
+        claims -> Nullable<Text>,
     }
 }
 
@@ -27,12 +46,95 @@ table! {
         scope -> Varchar,
         response_type -> Varchar,
         redirect_uri -> Varchar,
+        require_auth_time -> Bool,
+        jwks -> Nullable<Text>,
+        authorization_encrypted_response_alg -> Nullable<Varchar>,
+        authorization_encrypted_response_enc -> Nullable<Varchar>,
+        post_logout_redirect_uris -> Nullable<Text>,
+        backchannel_logout_uri -> Nullable<Varchar>,
+        backchannel_logout_session_required -> Bool,
+        frontchannel_logout_uri -> Nullable<Varchar>,
+        frontchannel_logout_session_required -> Bool,
+        request_object_signing_alg -> Nullable<Varchar>,
+        require_signed_request_object -> Bool,
+        request_uris -> Nullable<Text>,
+        require_pushed_authorization_requests -> Bool,
+        token_endpoint_auth_method -> Varchar,
+        tls_client_auth_subject_dn -> Nullable<Varchar>,
+        tls_client_auth_san_dns -> Nullable<Varchar>,
+        tls_client_certificate_bound_access_tokens -> Bool,
+        access_token_jwt -> Bool,
+        access_token_audience -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    grants (user_id, client_id) {
+        user_id -> Varchar,
+        client_id -> Varchar,
+        scope -> Varchar,
+        created_at -> Datetime,
+    }
+}
+
+table! {
+    login_failures (scope, subject) {
+        scope -> Varchar,
+        subject -> Varchar,
+        failures -> Integer,
+        last_failed_at -> Datetime,
+        locked_until -> Nullable<Datetime>,
+    }
+}
+
+table! {
+    logout_deliveries (id) {
+        id -> Unsigned<Bigint>,
+        sid -> Varchar,
+        client_id -> Varchar,
+        uri -> Varchar,
+        attempt -> Integer,
+        status -> Nullable<Integer>,
+        error -> Nullable<Text>,
+        delivered -> Bool,
+        created_at -> Datetime,
+    }
+}
+
+table! {
+    pushed_authorization_requests (request_uri) {
+        request_uri -> Varchar,
+        client_id -> Varchar,
+        params -> Text,
+        expires_at -> Datetime,
+    }
+}
+
+table! {
+    recovery_codes (user_id, code_hash) {
+        user_id -> Varchar,
+        code_hash -> Varchar,
     }
 }
 
 table! {
     session (session_id) {
         session_id -> Varchar,
+        user_id -> Varchar,
+        auth_time -> Datetime,
+        sid -> Varchar,
+        expires_at -> Datetime,
+        last_seen_at -> Datetime,
+        amr -> Varchar,
+        acr -> Varchar,
+        csrf_token -> Varchar,
+    }
+}
+
+table! {
+    session_clients (sid, client_id) {
+        sid -> Varchar,
+        client_id -> Varchar,
     }
 }
 
@@ -42,6 +144,41 @@ table! {
         user_id -> Varchar,
         scope -> Varchar,
         created_at -> Datetime,
+        client_id -> Varchar,
+        claims -> Nullable<Text>,
+        dpop_jkt -> Nullable<Varchar>,
+        x5t_s256 -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    totp_credentials (user_id) {
+        user_id -> Varchar,
+        secret -> Varchar,
+        confirmed -> Bool,
+        last_used_step -> Nullable<Bigint>,
+        created_at -> Datetime,
+    }
+}
+
+table! {
+    webauthn_challenges (challenge) {
+        challenge -> Varchar,
+        user_id -> Nullable<Varchar>,
+        login_challenge -> Nullable<Varchar>,
+        created_at -> Datetime,
+    }
+}
+
+table! {
+    webauthn_credentials (credential_id) {
+        credential_id -> Varchar,
+        user_id -> Varchar,
+        public_key -> Text,
+        sign_count -> Bigint,
+        backup_eligible -> Bool,
+        created_at -> Datetime,
+        last_used_at -> Nullable<Datetime>,
     }
 }
 
@@ -49,6 +186,15 @@ allow_tables_to_appear_in_same_query!(
     auth_challenges,
     auth_code,
     client,
+    grants,
+    login_failures,
+    logout_deliveries,
+    pushed_authorization_requests,
+    recovery_codes,
     session,
+    session_clients,
     tokens,
+    totp_credentials,
+    webauthn_challenges,
+    webauthn_credentials,
 );
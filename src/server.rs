@@ -1,47 +1,452 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
-use chrono::{Duration, Utc};
-use diesel::MysqlConnection;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{result::Error::NotFound, Connection, MysqlConnection};
 use rocket::{
+    fairing::AdHoc,
     figment::{
         util::map,
         value::{Map, Value},
     },
     form::Form,
-    http::CookieJar,
+    http::{Cookie, CookieJar, Status},
+    response::{status, Redirect},
     serde::json::Json,
+    State,
 };
 use rocket_dyn_templates::Template;
 
 use crate::{
-    context::{ConsentContext, ErrorContext, LoginContext},
+    acr::AcrPolicy,
+    backchannel::{self, LogoutTarget, LogoutToken},
+    config::{MtlsConfig, ProxyConfig, SessionConfig},
+    context::{
+        AccountContext, ConsentContext, ErrorContext, GrantContext, LoggedOutContext, LoginContext,
+        LogoutContext, MfaContext, RecoveryCodesContext, ScopeContext, SessionContext, TotpContext,
+        WebAuthnContext, WebAuthnCredentialContext,
+    },
+    dpop::{DpopProof, DpopState},
     error::CustomError,
+    lockout::{self, ClientIp},
     message::{
+        account::{
+            ConfirmTotpParams, LogoutOtherSessionsParams, RevokeGrantParams, SetupTotpParams,
+        },
         authentication::{
-            AuthenticationRequest, AuthenticationRequestParam, SuccessfulAuthenticationResponse,
+            AuthenticateResponse, AuthenticationRequest, AuthenticationRequestParam,
+            AuthorizationError, ErrorAuthenticationResponse, SuccessfulAuthenticationResponse,
         },
+        claims::{Claims, ClaimsRequest},
         client::ClientParams,
         consent::{ConsentGetParams, ConsentParams},
-        enums::{Scope, Scopes},
-        login::{LoginParams, RedirectWithCookie},
-        token::{Basic, IdToken, SuccessfulTokenResponse, TokenRequest},
+        discovery::{ProviderMetadata, ISSUER},
+        enums::{ResponseMode, ResponseType, ResponseTypes, Scopes},
+        introspection::{IntrospectionRequest, IntrospectionResponse},
+        jarm::Jarm,
+        login::{csrf_cookie, LoginParams, LoginResponse, MfaParams, RedirectWithCookie},
+        logout::{EndSessionParams, EndSessionResponse},
+        par::{
+            ErrorPushedAuthorizationResponse, SuccessfulPushedAuthorizationResponse, PAR_LIFETIME,
+            REQUEST_URI_PREFIX,
+        },
+        request_object,
+        token::{
+            AccessTokenClaims, ClientCredentials, Confirmation, IdToken, SuccessfulTokenResponse,
+            TokenRequest,
+        },
         userinfo::{Address, SuccessfulUserinfoResponse, UserinfoRequest},
+        webauthn::{
+            CreationOptions, DeleteCredentialParams, RegisterCredentialParams, RequestOptions,
+            WebAuthnLoginParams,
+        },
+    },
+    mfa::{self, AMR_HARDWARE_KEY, AMR_OTP, AMR_PASSWORD, AMR_SOFTWARE_KEY},
+    models::{
+        AuthChallenge, AuthCode, Client, NewGrant, NewToken, PushedAuthorizationRequest,
+        RecoveryCode, Session, SessionClient, TotpCredential, WebAuthnChallenge,
+        WebAuthnCredential,
     },
-    models::{AuthChallenge, AuthCode, Client, NewToken, Session},
+    mtls::{self, ClientCertificate},
+    redirect::RedirectBuilder,
     repository::{
-        self, create_auth_code, create_client, create_session, find_auth_challenge, find_session,
+        self, create_auth_code, create_client, create_session, find_auth_challenge, find_grant,
+        save_grant, update_auth_challenge_session,
     },
-    utils::generate_challenge,
+    session::AuthenticatedSession,
+    utils::{
+        generate_challenge, left_half_hash, origin, session_state, sign_jwt, sign_jwt_with_type,
+        verify_jwt,
+    },
+    webauthn::{self, Ceremony},
 };
 
 #[database("oidc_db")]
 pub struct DBPool(MysqlConnection);
 
+fn consent_url(challenge: &str, state: &Option<String>) -> String {
+    RedirectBuilder::new("/authorization")
+        .param("consent_challenge", challenge)
+        .param_opt("state", state)
+        .query()
+}
+
+fn mfa_url(challenge: &str, state: &Option<String>) -> String {
+    RedirectBuilder::new("/mfa")
+        .param("login_challenge", challenge)
+        .param_opt("state", state)
+        .query()
+}
+
+/// Loads a challenge that hasn't expired or been answered yet and that was
+/// issued to this browser, identified by its CSRF cookie
+fn find_pending_challenge(
+    challenge: &str,
+    browser_csrf_token: &Option<String>,
+    config: &SessionConfig,
+    conn: &MysqlConnection,
+) -> Result<AuthChallenge, CustomError> {
+    let challenge = find_auth_challenge(challenge, conn).or(Err(CustomError::ChallengeError))?;
+    if challenge.is_expired(config.challenge_lifetime) {
+        repository::delete_auth_challenge(challenge.challenge, conn)?;
+        return Err(CustomError::ChallengeError);
+    }
+    if challenge.consumed || browser_csrf_token.as_deref() != Some(challenge.csrf_token.as_str()) {
+        return Err(CustomError::ChallengeError);
+    }
+    Ok(challenge)
+}
+
+/// Loads a pending challenge whose login phase hasn't ended yet, so that a
+/// login form can't be submitted again to start another session
+fn find_login_challenge(
+    challenge: &str,
+    browser_csrf_token: &Option<String>,
+    config: &SessionConfig,
+    conn: &MysqlConnection,
+) -> Result<AuthChallenge, CustomError> {
+    let challenge = find_pending_challenge(challenge, browser_csrf_token, config, conn)?;
+    if challenge.is_logged_in() {
+        return Err(CustomError::ChallengeError);
+    }
+    Ok(challenge)
+}
+
+/// Checks the CSRF token submitted with the login or consent form
+fn check_csrf_token(challenge: &AuthChallenge, csrf_token: &str) -> Result<(), CustomError> {
+    if challenge.csrf_token.is_empty() || challenge.csrf_token != csrf_token {
+        return Err(CustomError::ChallengeError);
+    }
+    Ok(())
+}
+
+/// The user who entered the right password for the challenge and still has to
+/// enter the second factor: the challenge knows the user but has no session yet
+fn pending_mfa_user(challenge: &AuthChallenge) -> Result<String, CustomError> {
+    match (&challenge.user_id, &challenge.sid) {
+        (Some(user_id), None) => Ok(user_id.clone()),
+        _ => Err(CustomError::ChallengeError),
+    }
+}
+
+/// Ends the challenge with an error response to the client
+fn challenge_error(
+    challenge: &AuthChallenge,
+    error: AuthorizationError,
+    conn: &MysqlConnection,
+) -> Result<CustomError, CustomError> {
+    repository::consume_auth_challenge(&challenge.challenge, conn)?;
+    let client = repository::find_client(&challenge.client_id, conn)?;
+    let response_mode =
+        ResponseMode::from_str(&challenge.response_mode).or(Err(CustomError::BadRequest))?;
+    Ok(CustomError::AuthenticationError(Box::new(
+        ErrorAuthenticationResponse::new(&challenge.redirect_uri, error, &challenge.state)
+            .response_mode(response_mode)
+            .jarm(Jarm::new(&client)),
+    )))
+}
+
+/// A consent challenge may only be answered by the session that logged in for it
+fn check_challenge_owner(
+    challenge: &AuthChallenge,
+    session: &AuthenticatedSession,
+) -> Result<(), CustomError> {
+    match &challenge.sid {
+        Some(sid) if sid == &session.session.sid => Ok(()),
+        _ => Err(CustomError::SessionError),
+    }
+}
+
+/// The end-user's claims other than `sub` (sample data)
+fn user_claims(_user_id: &str) -> Claims {
+    let address = Address {
+        formatted: "formatted address".to_string(),
+        street_address: "street address".to_string(),
+        locality: "locality".to_string(),
+        region: "region".to_string(),
+        postal_code: "postal code".to_string(),
+        country: "country".to_string(),
+    };
+    let mut claims = Claims::new();
+    claims.insert("name".to_string(), "tarou tanaka".into());
+    claims.insert("email".to_string(), "test@example.com".into());
+    claims.insert("email_verified".to_string(), true.into());
+    claims.insert(
+        "address".to_string(),
+        serde_json::to_value(address).unwrap_or_default(),
+    );
+    claims.insert("phone_number".to_string(), "111-1234-5678".into());
+    claims.insert("phone_number_verified".to_string(), true.into());
+    claims
+}
+
+/// Requested claims about the end-user that no requested scope covers.
+/// The end-user has to consent to them on top of the scopes.
+fn extra_claims(claims: &Option<ClaimsRequest>, scopes: &Scopes, user_id: &str) -> Vec<String> {
+    let available = user_claims(user_id);
+    let scope_claims = scopes.claims();
+    claims
+        .as_ref()
+        .map(|c| c.names())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| available.contains_key(name) && !scope_claims.contains(&name.as_str()))
+        .collect()
+}
+
+/// `claims` is the `claims` parameter, kept for the userinfo endpoint.
+/// Clients that registered for it get a signed JWT, stored by its `jti`.
+/// https://www.rfc-editor.org/rfc/rfc9068.html
+fn issue_access_token(
+    user_id: &str,
+    client: &Client,
+    scope: &str,
+    claims: &Option<String>,
+    cnf: Confirmation,
+    conn: &MysqlConnection,
+) -> Result<String, CustomError> {
+    let token_id = generate_challenge();
+    let access_token = if client.access_token_jwt {
+        let now = Utc::now();
+        sign_jwt_with_type(
+            &AccessTokenClaims {
+                iss: ISSUER.to_string(),
+                sub: user_id.to_string(),
+                aud: client
+                    .access_token_audience
+                    .clone()
+                    .unwrap_or(format!("{}/userinfo", ISSUER)),
+                client_id: client.client_id.clone(),
+                scope: scope.to_string(),
+                jti: token_id.clone(),
+                exp: (now + Duration::hours(1)).timestamp(),
+                iat: now.timestamp(),
+                cnf: cnf.clone(),
+            },
+            "at+jwt",
+        )?
+    } else {
+        token_id.clone()
+    };
+    repository::create_token(
+        NewToken {
+            access_token: token_id,
+            user_id: user_id.to_string(),
+            scope: scope.to_string(),
+            client_id: client.client_id.clone(),
+            claims: claims.clone(),
+            dpop_jkt: cnf.jkt,
+            x5t_s256: cnf.x5t_s256,
+        },
+        conn,
+    )?;
+    Ok(access_token)
+}
+
+/// What an access token is stored by: the `jti` of a JWT access token this
+/// provider signed, otherwise the opaque token itself
+fn access_token_id(access_token: &str) -> String {
+    let is_jwt = jsonwebtoken::decode_header(access_token)
+        .ok()
+        .and_then(|h| h.typ)
+        .filter(|typ| typ == "at+jwt")
+        .is_some();
+    match verify_jwt::<AccessTokenClaims>(access_token) {
+        Ok(claims) if is_jwt => claims.jti,
+        _ => access_token.to_string(),
+    }
+}
+
+fn id_token_claims(
+    client: &Client,
+    user_id: &str,
+    nonce: &str,
+    auth_time: NaiveDateTime,
+    max_age: Option<u64>,
+    sid: &Option<String>,
+    claims: &Option<ClaimsRequest>,
+) -> IdToken {
+    let now = Utc::now();
+    let exp = now + Duration::hours(12);
+    // auth_time is required when max_age was requested or the client asks for it,
+    // either at registration or through the claims parameter
+    let auth_time = if max_age.is_some()
+        || client.require_auth_time
+        || claims
+            .as_ref()
+            .filter(|c| c.requests_id_token_claim("auth_time"))
+            .is_some()
+    {
+        Some(auth_time.timestamp() as usize)
+    } else {
+        None
+    };
+    IdToken {
+        iss: ISSUER.to_string(),
+        sub: user_id.to_string(),
+        aud: client.client_id.clone(),
+        exp: exp.timestamp() as usize,
+        iat: now.timestamp() as usize,
+        nonce: nonce.to_string(),
+        auth_time,
+        at_hash: None,
+        c_hash: None,
+        sid: sid.to_owned(),
+        amr: None,
+        acr: None,
+        claims: claims
+            .as_ref()
+            .map(|c| c.id_token_claims(&user_claims(user_id)))
+            .unwrap_or_default(),
+    }
+}
+
+/// Splits the space-separated methods stored with the session into the `amr` claim
+fn amr_claim(amr: &Option<String>) -> Option<Vec<String>> {
+    amr.as_ref()
+        .map(|a| a.split_whitespace().map(String::from).collect())
+}
+
+/// Issues the artifacts the challenge's response_type asks for:
+/// an authorization code, an access token and/or an ID token
+fn issue_authorization_response(
+    challenge: &AuthChallenge,
+    browser_state: &Option<String>,
+    conn: &MysqlConnection,
+) -> Result<SuccessfulAuthenticationResponse, CustomError> {
+    // a challenge yields a single response; replaying it must fail
+    if repository::consume_auth_challenge(&challenge.challenge, conn)? == 0 {
+        return Err(CustomError::ChallengeError);
+    }
+    let user_id = challenge.user_id.clone().ok_or(CustomError::SessionError)?;
+    let auth_time = challenge.auth_time.ok_or(CustomError::SessionError)?;
+    let client = repository::find_client(&challenge.client_id, conn)?;
+    let response_type =
+        ResponseTypes::from_str(&challenge.response_type).or(Err(CustomError::BadRequest))?;
+    let nonce = challenge.nonce.clone().unwrap_or("".to_string());
+    let claims = challenge.claims_request();
+    // a response about anyone but the end-user the client named must not be returned
+    if let Some(sub) = claims.as_ref().and_then(|c| c.sub()) {
+        if sub.as_str() != Some(user_id.as_str()) {
+            return Err(challenge_error(
+                challenge,
+                AuthorizationError::LoginRequired,
+                conn,
+            )?);
+        }
+    }
+    let mut claim = id_token_claims(
+        &client,
+        &user_id,
+        &nonce,
+        auth_time,
+        challenge.max_age,
+        &challenge.sid,
+        &claims,
+    );
+    claim.amr = amr_claim(&challenge.amr);
+    claim.acr = challenge.acr.clone();
+    // remember the relying parties that took part in the session for logout
+    if let Some(sid) = &challenge.sid {
+        repository::save_session_client(
+            SessionClient {
+                sid: sid.clone(),
+                client_id: client.client_id.clone(),
+            },
+            conn,
+        )?;
+    }
+    let response_mode =
+        ResponseMode::from_str(&challenge.response_mode).or(Err(CustomError::BadRequest))?;
+    let mut res = SuccessfulAuthenticationResponse::new(
+        &challenge.redirect_uri,
+        &challenge.state,
+        response_mode,
+    )
+    .jarm(Jarm::new(&client));
+    // lets the client watch the session through the check_session iframe
+    if let (Some(bs), Some(origin)) = (browser_state, origin(&challenge.redirect_uri)) {
+        res = res.session_state(&session_state(
+            &client.client_id,
+            &origin,
+            bs,
+            &generate_challenge(),
+        ));
+    }
+    if response_type.contains(&ResponseType::Code) {
+        let auth_code = generate_challenge();
+        create_auth_code(
+            AuthCode {
+                code: auth_code.clone(),
+                client_id: challenge.client_id.clone(),
+                user_id: user_id.clone(),
+                scope: challenge.scope.clone(),
+                nonce: nonce.clone(),
+                auth_time,
+                max_age: challenge.max_age,
+                sid: challenge.sid.clone(),
+                amr: challenge.amr.clone(),
+                acr: challenge.acr.clone(),
+                claims: challenge.claims.clone(),
+            },
+            conn,
+        )?;
+        claim.c_hash = Some(left_half_hash(&auth_code));
+        res = res.code(&auth_code);
+    }
+    if response_type.contains(&ResponseType::Token) {
+        let access_token = issue_access_token(
+            &user_id,
+            &client,
+            &challenge.scope,
+            &challenge.claims,
+            Confirmation::default(),
+            conn,
+        )?;
+        claim.at_hash = Some(left_half_hash(&access_token));
+        res = res.access_token(&access_token, 3600);
+    }
+    if response_type.contains(&ResponseType::IdToken) {
+        res = res.id_token(&sign_jwt(&claim)?);
+    }
+    Ok(res)
+}
+
 #[get("/")]
 async fn index() -> &'static str {
     "Hello, world!"
 }
 
+#[get("/.well-known/openid-configuration")]
+async fn get_configuration(acr_policy: &State<AcrPolicy>) -> Json<ProviderMetadata> {
+    Json(ProviderMetadata::new(acr_policy))
+}
+
+/// OpenID Connect Session Management check_session_iframe
+#[get("/check_session")]
+async fn get_check_session() -> Template {
+    Template::render("check_session", HashMap::<&str, &str>::new())
+}
+
 #[get("/client?<clientparam..>")]
 async fn get_client(
     clientparam: Option<ClientParams>,
@@ -58,6 +463,39 @@ async fn get_client(
                     scope: param.scope,
                     response_type: param.response_type,
                     redirect_uri: param.redirect_uri,
+                    require_auth_time: param.require_auth_time.unwrap_or(false),
+                    jwks: param.jwks,
+                    authorization_encrypted_response_alg: param
+                        .authorization_encrypted_response_alg,
+                    authorization_encrypted_response_enc: param
+                        .authorization_encrypted_response_enc,
+                    post_logout_redirect_uris: param.post_logout_redirect_uris,
+                    backchannel_logout_uri: param.backchannel_logout_uri,
+                    backchannel_logout_session_required: param
+                        .backchannel_logout_session_required
+                        .unwrap_or(false),
+                    frontchannel_logout_uri: param.frontchannel_logout_uri,
+                    frontchannel_logout_session_required: param
+                        .frontchannel_logout_session_required
+                        .unwrap_or(false),
+                    request_object_signing_alg: param.request_object_signing_alg,
+                    require_signed_request_object: param
+                        .require_signed_request_object
+                        .unwrap_or(false),
+                    request_uris: param.request_uris,
+                    require_pushed_authorization_requests: param
+                        .require_pushed_authorization_requests
+                        .unwrap_or(false),
+                    token_endpoint_auth_method: param
+                        .token_endpoint_auth_method
+                        .unwrap_or(String::from("client_secret_basic")),
+                    tls_client_auth_subject_dn: param.tls_client_auth_subject_dn,
+                    tls_client_auth_san_dns: param.tls_client_auth_san_dns,
+                    tls_client_certificate_bound_access_tokens: param
+                        .tls_client_certificate_bound_access_tokens
+                        .unwrap_or(false),
+                    access_token_jwt: param.access_token_jwt.unwrap_or(false),
+                    access_token_audience: param.access_token_audience,
                 },
                 c,
             )?;
@@ -71,114 +509,749 @@ async fn get_client(
     .await
 }
 
+/// Sends an error for a request that failed before it was fully validated
+/// back to the client, unless its redirect_uri isn't one the client registered
+fn request_error(
+    param: &AuthenticationRequestParam,
+    client: &Client,
+    error: AuthorizationError,
+) -> CustomError {
+    let redirect_uri = param.redirect_uri.as_deref().unwrap_or("");
+    if client.check_redirect_uri(redirect_uri).is_err() {
+        return CustomError::RedirectUriError;
+    }
+    CustomError::AuthenticationError(Box::new(
+        ErrorAuthenticationResponse::new(redirect_uri, error, &param.state).jarm(Jarm::new(client)),
+    ))
+}
+
+/// Applies the request object passed by value or by reference to the request.
+/// Only request_uris the client registered are fetched.
+async fn resolve_request_object(
+    param: AuthenticationRequestParam,
+    client: &Client,
+) -> Result<AuthenticationRequestParam, CustomError> {
+    let request = match (&param.request, &param.request_uri) {
+        (Some(_), Some(_)) => {
+            return Err(request_error(
+                &param,
+                client,
+                AuthorizationError::InvalidRequest,
+            ))
+        }
+        (Some(request), None) => Some(request.clone()),
+        (None, Some(uri)) => {
+            client.check_request_uri(uri).or(Err(request_error(
+                &param,
+                client,
+                AuthorizationError::InvalidRequestUri,
+            )))?;
+            Some(request_object::fetch(uri).await.map_err(|e| {
+                log::warn!("failed to fetch the request object from {}: {}", uri, e);
+                request_error(&param, client, AuthorizationError::InvalidRequestUri)
+            })?)
+        }
+        (None, None) => None,
+    };
+    match request {
+        Some(request) => {
+            let object = request_object::decode(&request, client).map_err(|e| {
+                log::info!("invalid request object: {}", e);
+                request_error(&param, client, AuthorizationError::InvalidRequestObject)
+            })?;
+            Ok(param.with_request_object(object))
+        }
+        None if client.require_signed_request_object => Err(request_error(
+            &param,
+            client,
+            AuthorizationError::InvalidRequest,
+        )),
+        None => Ok(param),
+    }
+}
+
+/// The parameters pushed to `/par` for the request_uri, if the client pushed them
+/// and they are still valid. Each request_uri can be redeemed once.
+fn redeem_pushed_request(
+    request_uri: &str,
+    client_id: &str,
+    conn: &MysqlConnection,
+) -> Result<Option<AuthenticationRequestParam>, CustomError> {
+    let pushed = match repository::find_pushed_authorization_request(request_uri, conn) {
+        Ok(p) if p.client_id == client_id => p,
+        Ok(_) | Err(NotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if repository::delete_pushed_authorization_request(request_uri, conn)? == 0
+        || pushed.is_expired()
+    {
+        return Ok(None);
+    }
+    Ok(serde_json::from_str(&pushed.params).ok())
+}
+
+/// Errors at `/par` go back to the client in the response rather than through a redirect
+fn pushed_error(e: CustomError) -> CustomError {
+    match e {
+        CustomError::AuthenticationError(e) => {
+            CustomError::PushedAuthorizationError(ErrorPushedAuthorizationResponse::new(e.error()))
+        }
+        CustomError::RedirectUriError => CustomError::PushedAuthorizationError(
+            ErrorPushedAuthorizationResponse::new(&AuthorizationError::InvalidRequest),
+        ),
+        e => e,
+    }
+}
+
+/// Pushed authorization request: the client sends the authentication request
+/// directly and gets a request_uri to redirect the browser with instead
+/// https://www.rfc-editor.org/rfc/rfc9126.html
+#[post("/par", data = "<authparam>")]
+async fn post_par(
+    authparam: Form<AuthenticationRequestParam>,
+    credentials: ClientCredentials,
+    conn: DBPool,
+) -> Result<status::Custom<Json<SuccessfulPushedAuthorizationResponse>>, CustomError> {
+    let mut authparam = authparam.into_inner();
+    let client_id = credentials
+        .client_id(authparam.client_id.as_deref())
+        .unwrap_or_default()
+        .to_string();
+    let client = conn
+        .run(move |c| repository::find_client(&client_id, c))
+        .await
+        .or(Err(CustomError::UnauthorizedError))?;
+    if !credentials.authenticate(&client, authparam.client_id.as_deref()) {
+        return Err(CustomError::UnauthorizedError);
+    }
+    // a request_uri is what this endpoint hands out, not what it takes
+    if authparam.request_uri.is_some()
+        || authparam
+            .client_id
+            .as_ref()
+            .filter(|c| *c != &client.client_id)
+            .is_some()
+    {
+        return Err(CustomError::PushedAuthorizationError(
+            ErrorPushedAuthorizationResponse::new(&AuthorizationError::InvalidRequest),
+        ));
+    }
+    authparam.client_id = Some(client.client_id.clone());
+    let authparam = resolve_request_object(authparam, &client)
+        .await
+        .map_err(pushed_error)?;
+    conn.run(move |c| {
+        // validated now so that the client learns about errors without involving the browser
+        AuthenticationRequest::from(authparam.clone(), &client).map_err(pushed_error)?;
+        let request_uri = format!("{}{}", REQUEST_URI_PREFIX, generate_challenge());
+        repository::create_pushed_authorization_request(
+            PushedAuthorizationRequest {
+                request_uri: request_uri.clone(),
+                client_id: client.client_id,
+                params: serde_json::to_string(&authparam).or(Err(CustomError::BadRequest))?,
+                expires_at: Utc::now().naive_utc() + Duration::seconds(PAR_LIFETIME),
+            },
+            c,
+        )?;
+        Ok(status::Custom(
+            Status::Created,
+            Json(SuccessfulPushedAuthorizationResponse {
+                request_uri,
+                expires_in: PAR_LIFETIME,
+            }),
+        ))
+    })
+    .await
+}
+
 #[get("/authenticate?<authparam..>")]
 async fn get_authenticate(
     authparam: AuthenticationRequestParam,
+    session: Option<AuthenticatedSession>,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
+    acr_policy: &State<AcrPolicy>,
     conn: DBPool,
-) -> Result<Template, CustomError> {
+) -> Result<AuthenticateResponse, CustomError> {
+    let acr_policy = acr_policy.inner().clone();
+    let browser_state = jar.get("browser_state").map(|s| s.value().to_string());
+    let csrf_token = jar
+        .get_private("csrf_token")
+        .map(|c| c.value().to_string())
+        .unwrap_or_else(generate_challenge);
+    jar.add_private(csrf_cookie(
+        csrf_token.clone(),
+        config.session_cookie_secure,
+    ));
+    let client_id = authparam.client_id.clone().unwrap_or_default();
+    let client = conn
+        .run(move |c| repository::find_client(&client_id, c))
+        .await?;
+    let authparam = match authparam
+        .request_uri
+        .clone()
+        .filter(|u| u.starts_with(REQUEST_URI_PREFIX))
+    {
+        Some(request_uri) => {
+            let client_id = client.client_id.clone();
+            conn.run(move |c| redeem_pushed_request(&request_uri, &client_id, c))
+                .await?
+                .ok_or_else(|| {
+                    request_error(&authparam, &client, AuthorizationError::InvalidRequestUri)
+                })?
+        }
+        None if client.require_pushed_authorization_requests => {
+            return Err(request_error(
+                &authparam,
+                &client,
+                AuthorizationError::InvalidRequest,
+            ))
+        }
+        None => resolve_request_object(authparam, &client).await?,
+    };
     conn.run(move |c| {
-        let client =
-            repository::find_client(&authparam.clone().client_id.unwrap_or("".to_string()), c)?;
         let authparam = AuthenticationRequest::from(authparam, &client)?;
         let state = authparam.state().clone();
+        let requested_acr: Vec<String> = authparam.acr_values().clone();
+        let requested_acr: Vec<&str> = requested_acr.iter().map(String::as_str).collect();
+        if authparam.acr_essential() && !acr_policy.is_supported(&requested_acr) {
+            return Err(CustomError::AuthenticationError(Box::new(
+                ErrorAuthenticationResponse::new(
+                    authparam.redirect_uri(),
+                    AuthorizationError::UnmetAuthenticationRequirements,
+                    &state,
+                )
+                .response_mode(*authparam.response_mode())
+                .jarm(Jarm::new(&client)),
+            )));
+        }
         let challenge = generate_challenge();
-        repository::create_auth_challenge(
-            AuthChallenge::from_auth_request(&challenge, authparam),
-            c,
+        // single sign-on: an existing valid session skips the login page
+        // unless the end-user authenticated longer ago than max_age allows
+        let session = session
+            .map(|s| s.session)
+            .filter(|s| s.satisfies_max_age(*authparam.max_age()))
+            // the client named another end-user through the claims parameter
+            .filter(
+                |s| match authparam.claims().as_ref().and_then(|c| c.sub()) {
+                    Some(sub) => sub.as_str() == Some(s.user_id.as_str()),
+                    None => true,
+                },
+            );
+        // step-up: a session below the requested acr has to authenticate again
+        let (session, step_up) = match session {
+            Some(s)
+                if !acr_policy.satisfies(
+                    &requested_acr,
+                    &s.amr.split_whitespace().collect::<Vec<_>>(),
+                ) =>
+            {
+                (None, Some(s))
+            }
+            s => (s, None),
+        };
+        // grants only record scopes, so claims beyond them need consent every time
+        let granted = match &session {
+            Some(s) => {
+                find_grant(&s.user_id, authparam.client_id(), c)
+                    .map(|g| g.covers(authparam.scope()))
+                    .unwrap_or(false)
+                    && extra_claims(authparam.claims(), authparam.scope(), &s.user_id).is_empty()
+            }
+            None => false,
+        };
+        let mut auth_challenge =
+            AuthChallenge::from_auth_request(&challenge, &csrf_token, authparam);
+        auth_challenge.user_id = session.as_ref().map(|s| s.user_id.clone());
+        auth_challenge.auth_time = session.as_ref().map(|s| s.auth_time);
+        auth_challenge.sid = session.as_ref().map(|s| s.sid.clone());
+        auth_challenge.amr = session.as_ref().map(|s| s.amr.clone());
+        auth_challenge.acr = session.as_ref().and_then(|s| {
+            let amr: Vec<&str> = s.amr.split_whitespace().collect();
+            acr_policy.acr(&requested_acr, &amr).map(String::from)
+        });
+        // the session's factors count towards the step-up when only the code is missing
+        if let Some(s) = &step_up {
+            let mut amr: Vec<&str> = s.amr.split_whitespace().collect();
+            if !amr.contains(&AMR_OTP) {
+                amr.push(AMR_OTP);
+            }
+            if acr_policy.satisfies(&requested_acr, &amr) && mfa::is_enrolled(&s.user_id, c)? {
+                auth_challenge.user_id = Some(s.user_id.clone());
+                auth_challenge.amr = Some(s.amr.clone());
+                repository::create_auth_challenge(auth_challenge, c)?;
+                return Ok(AuthenticateResponse::SecondFactor(Redirect::to(mfa_url(
+                    &challenge, &state,
+                ))));
+            }
+        }
+        repository::create_auth_challenge(auth_challenge, c)?;
+        match session {
+            Some(_) if granted => {
+                let auth_challenge = find_auth_challenge(&challenge, c)?;
+                Ok(AuthenticateResponse::Authorized(
+                    issue_authorization_response(&auth_challenge, &browser_state, c)?,
+                ))
+            }
+            Some(_) => Ok(AuthenticateResponse::Consent(Redirect::to(consent_url(
+                &challenge, &state,
+            )))),
+            None => Ok(AuthenticateResponse::Login(Template::render(
+                "login",
+                &LoginContext {
+                    error_msg: None,
+                    login_challenge: challenge,
+                    csrf_token,
+                    state,
+                    locked_until: None,
+                },
+            ))),
+        }
+    })
+    .await
+}
+
+/// Starts a session for the end-user who authenticated for the challenge.
+/// Also returns the relying parties to notify when another user's session had to end.
+/// Fails with `unmet_authentication_requirements` when the client requested
+/// an `acr` as essential that these authentication methods don't satisfy.
+fn start_session(
+    challenge: &AuthChallenge,
+    user_id: String,
+    amr: &[&str],
+    previous: Option<AuthenticatedSession>,
+    config: &SessionConfig,
+    acr_policy: &AcrPolicy,
+    conn: &MysqlConnection,
+) -> Result<(RedirectWithCookie, Vec<LogoutTarget>), CustomError> {
+    let requested_acr = challenge.requested_acr();
+    if challenge.acr_essential && !acr_policy.satisfies(&requested_acr, amr) {
+        return Err(challenge_error(
+            challenge,
+            AuthorizationError::UnmetAuthenticationRequirements,
+            conn,
+        )?);
+    }
+    let (session_id, max_age, targets) = conn.transaction::<_, CustomError, _>(|| {
+        // always issue a new session id on login so that an id planted
+        // in the browser beforehand can't be used (session fixation)
+        let mut targets = vec![];
+        let sid = match previous.map(|p| p.session) {
+            // re-authentication keeps the sid relying parties already know
+            Some(previous) if previous.user_id == user_id => {
+                repository::delete_session(previous.session_id, conn)?;
+                previous.sid
+            }
+            Some(previous) => {
+                targets = end_session(previous, conn)?.backchannel;
+                generate_challenge()
+            }
+            None => generate_challenge(),
+        };
+        let session_id = generate_challenge();
+        let now = Utc::now().naive_utc();
+        let session = Session {
+            session_id: session_id.clone(),
+            user_id,
+            auth_time: now,
+            sid,
+            expires_at: now + Duration::seconds(config.session_absolute_lifetime),
+            last_seen_at: now,
+            amr: amr.join(" "),
+            acr: acr_policy.acr(&[], amr).unwrap_or_default().to_string(),
+            csrf_token: generate_challenge(),
+        };
+        // ends the challenge's login phase in the same transaction that creates the session
+        let updated = update_auth_challenge_session(
+            &challenge.challenge,
+            &session,
+            acr_policy.acr(&requested_acr, amr),
+            conn,
         )?;
+        if updated == 0 {
+            return Err(CustomError::ChallengeError);
+        }
+        let max_age = session.cookie_max_age(config.session_idle_timeout);
+        create_session(session, conn)?;
+        Ok((session_id, max_age, targets))
+    })?;
+    Ok((
+        RedirectWithCookie {
+            value: session_id,
+            browser_state: generate_challenge(),
+            max_age,
+            secure: config.session_cookie_secure,
+            next: consent_url(&challenge.challenge, &challenge.state),
+        },
+        targets,
+    ))
+}
+
+fn format_locked_until(locked_until: Option<NaiveDateTime>) -> Option<String> {
+    locked_until.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// Renders the login page again after a failed attempt
+fn login_error(
+    login_challenge: &str,
+    csrf_token: &str,
+    state: &Option<String>,
+    error_msg: Option<&str>,
+    locked_until: Option<NaiveDateTime>,
+) -> CustomError {
+    CustomError::ValidationError(Template::render(
+        "login",
+        &LoginContext {
+            error_msg: error_msg.map(String::from),
+            login_challenge: login_challenge.to_string(),
+            csrf_token: csrf_token.to_string(),
+            state: state.clone(),
+            locked_until: format_locked_until(locked_until),
+        },
+    ))
+}
+
+#[post("/authenticate", data = "<loginparam>")]
+async fn post_authenticate(
+    loginparam: Form<LoginParams>,
+    previous: Option<AuthenticatedSession>,
+    ip: ClientIp,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
+    acr_policy: &State<AcrPolicy>,
+    conn: DBPool,
+) -> Result<LoginResponse, CustomError> {
+    let config = *config.inner();
+    let acr_policy = acr_policy.inner().clone();
+    let browser_csrf_token = jar.get_private("csrf_token").map(|c| c.value().to_string());
+    let (res, targets) = conn
+        .run(move |c| {
+            let challenge =
+                find_login_challenge(&loginparam.login_challenge, &browser_csrf_token, &config, c)?;
+            check_csrf_token(&challenge, &loginparam.csrf_token)?;
+            let login_error = |error_msg: Option<&str>, locked_until: Option<NaiveDateTime>| {
+                login_error(
+                    &loginparam.login_challenge,
+                    &loginparam.csrf_token,
+                    &loginparam.state,
+                    error_msg,
+                    locked_until,
+                )
+            };
+            // refuse the attempt before looking at the password while backing off
+            if let Some(until) = lockout::blocked_until(&loginparam.username, ip.0, &config, c)? {
+                return Err(login_error(None, Some(until)));
+            }
+            if loginparam.username == "foobar".to_string() && loginparam.password == "1234" {
+                lockout::clear(&loginparam.username, c)?;
+                let user_id = String::from("userid"); // dummy user id
+                if mfa::is_enrolled(&user_id, c)? {
+                    repository::update_auth_challenge_user(
+                        &loginparam.login_challenge,
+                        &user_id,
+                        AMR_PASSWORD,
+                        c,
+                    )?;
+                    return Ok((
+                        LoginResponse::SecondFactor(Redirect::to(mfa_url(
+                            &loginparam.login_challenge,
+                            &loginparam.state,
+                        ))),
+                        vec![],
+                    ));
+                }
+                let (res, targets) = start_session(
+                    &challenge,
+                    user_id,
+                    &[AMR_PASSWORD],
+                    previous,
+                    &config,
+                    &acr_policy,
+                    c,
+                )?;
+                Ok((LoginResponse::LoggedIn(res), targets))
+            } else {
+                let locked_until = lockout::record_failure(&loginparam.username, ip.0, &config, c)?;
+                Err(login_error(
+                    Some("username or password is incorrect"),
+                    locked_until,
+                ))
+            }
+        })
+        .await?;
+    notify_backchannel_logout(targets, conn);
+    Ok(res)
+}
+
+#[get("/mfa?<login_challenge>&<state>")]
+async fn get_mfa(
+    login_challenge: String,
+    state: Option<String>,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
+    conn: DBPool,
+) -> Result<Template, CustomError> {
+    let config = *config.inner();
+    let browser_csrf_token = jar.get_private("csrf_token").map(|c| c.value().to_string());
+    conn.run(move |c| {
+        let challenge = find_pending_challenge(&login_challenge, &browser_csrf_token, &config, c)?;
+        pending_mfa_user(&challenge)?;
         Ok(Template::render(
-            "login",
-            &LoginContext {
+            "mfa",
+            &MfaContext {
                 error_msg: None,
-                login_challenge: challenge,
+                login_challenge,
+                csrf_token: challenge.csrf_token,
                 state,
+                locked_until: None,
             },
         ))
     })
     .await
 }
 
-#[post("/authenticate", data = "<loginparam>")]
-async fn post_authenticate(
-    loginparam: Form<LoginParams>,
+#[post("/mfa", data = "<mfaparam>")]
+async fn post_mfa(
+    mfaparam: Form<MfaParams>,
+    previous: Option<AuthenticatedSession>,
+    ip: ClientIp,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
+    acr_policy: &State<AcrPolicy>,
     conn: DBPool,
 ) -> Result<RedirectWithCookie, CustomError> {
+    let config = *config.inner();
+    let acr_policy = acr_policy.inner().clone();
+    let browser_csrf_token = jar.get_private("csrf_token").map(|c| c.value().to_string());
+    let (res, targets) = conn
+        .run(move |c| {
+            let challenge =
+                find_pending_challenge(&mfaparam.login_challenge, &browser_csrf_token, &config, c)?;
+            check_csrf_token(&challenge, &mfaparam.csrf_token)?;
+            let user_id = pending_mfa_user(&challenge)?;
+            let mfa_error = |error_msg: Option<&str>, locked_until: Option<NaiveDateTime>| {
+                CustomError::ValidationError(Template::render(
+                    "mfa",
+                    &MfaContext {
+                        error_msg: error_msg.map(String::from),
+                        login_challenge: mfaparam.login_challenge.to_string(),
+                        csrf_token: mfaparam.csrf_token.to_string(),
+                        state: mfaparam.state.clone(),
+                        locked_until: format_locked_until(locked_until),
+                    },
+                ))
+            };
+            // codes are guessable too, so they count against the same lockout
+            if let Some(until) = lockout::blocked_until(&user_id, ip.0, &config, c)? {
+                return Err(mfa_error(None, Some(until)));
+            }
+            if !mfa::verify_second_factor(&user_id, &mfaparam.code, c)? {
+                let locked_until = lockout::record_failure(&user_id, ip.0, &config, c)?;
+                return Err(mfa_error(Some("the code is incorrect"), locked_until));
+            }
+            lockout::clear(&user_id, c)?;
+            // the password, or the session's factors when stepping up, and the code
+            let mut amr = challenge.amr();
+            if !amr.contains(&AMR_OTP) {
+                amr.push(AMR_OTP);
+            }
+            start_session(&challenge, user_id, &amr, previous, &config, &acr_policy, c)
+        })
+        .await?;
+    notify_backchannel_logout(targets, conn);
+    Ok(res)
+}
+
+/// Loads a WebAuthn challenge and deletes it, so that a response can't be replayed
+fn take_webauthn_challenge(
+    challenge: &str,
+    config: &SessionConfig,
+    conn: &MysqlConnection,
+) -> Result<WebAuthnChallenge, CustomError> {
+    let found = repository::find_webauthn_challenge(challenge, conn)
+        .or(Err(CustomError::ChallengeError))?;
+    if repository::delete_webauthn_challenge(challenge, conn)? == 0
+        || found.is_expired(config.challenge_lifetime)
+    {
+        return Err(CustomError::ChallengeError);
+    }
+    Ok(found)
+}
+
+fn webauthn_ceremony<'a>(challenge: &'a str, origin: &'a str, rp_id: &'a str) -> Ceremony<'a> {
+    Ceremony {
+        challenge,
+        origin,
+        rp_id,
+    }
+}
+
+/// Starts a passkey login for the login challenge
+#[get("/webauthn/login?<login_challenge>")]
+async fn get_webauthn_login(
+    login_challenge: String,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
+    conn: DBPool,
+) -> Result<Json<RequestOptions>, CustomError> {
+    let config = *config.inner();
+    let browser_csrf_token = jar.get_private("csrf_token").map(|c| c.value().to_string());
     conn.run(move |c| {
-        if find_auth_challenge(&loginparam.login_challenge, c).is_err() {
-            return Err(CustomError::ValidationError(Template::render(
-                "error",
-                &ErrorContext {
-                    error_msg: String::from("Challenge is incorrect."),
-                },
-            )));
-        }
-        if loginparam.username == "foobar".to_string() && loginparam.password == "1234" {
-            let session_id = generate_challenge();
-            create_session(
-                Session {
-                    session_id: session_id.clone(),
-                },
+        find_login_challenge(&login_challenge, &browser_csrf_token, &config, c)?;
+        let challenge = webauthn::new_challenge();
+        repository::create_webauthn_challenge(
+            WebAuthnChallenge {
+                challenge: challenge.clone(),
+                user_id: None,
+                login_challenge: Some(login_challenge),
+                created_at: Utc::now().naive_utc(),
+            },
+            c,
+        )?;
+        Ok(Json(RequestOptions::new(&challenge)))
+    })
+    .await
+}
+
+#[post("/webauthn/login", data = "<loginparam>")]
+async fn post_webauthn_login(
+    loginparam: Form<WebAuthnLoginParams>,
+    previous: Option<AuthenticatedSession>,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
+    acr_policy: &State<AcrPolicy>,
+    conn: DBPool,
+) -> Result<RedirectWithCookie, CustomError> {
+    let config = *config.inner();
+    let acr_policy = acr_policy.inner().clone();
+    let browser_csrf_token = jar.get_private("csrf_token").map(|c| c.value().to_string());
+    let (res, targets) = conn
+        .run(move |c| {
+            let challenge =
+                find_login_challenge(&loginparam.login_challenge, &browser_csrf_token, &config, c)?;
+            check_csrf_token(&challenge, &loginparam.csrf_token)?;
+            let failed = || {
+                login_error(
+                    &loginparam.login_challenge,
+                    &loginparam.csrf_token,
+                    &loginparam.state,
+                    Some("the passkey couldn't be verified"),
+                    None,
+                )
+            };
+            let ceremony = take_webauthn_challenge(&loginparam.challenge, &config, c)?;
+            if ceremony.login_challenge.as_deref() != Some(loginparam.login_challenge.as_str()) {
+                return Err(CustomError::ChallengeError);
+            }
+            let credential =
+                match repository::find_webauthn_credential(&loginparam.credential_id, c) {
+                    Ok(credential) => credential,
+                    Err(NotFound) => return Err(failed()),
+                    Err(e) => return Err(e.into()),
+                };
+            // when the authenticator names the account, it has to be the passkey's owner
+            if let Some(user_handle) = &loginparam.user_handle {
+                if webauthn::decode(user_handle).as_deref() != Some(credential.user_id.as_bytes()) {
+                    return Err(failed());
+                }
+            }
+            let (client_data_json, authenticator_data, signature, public_key) = match (
+                webauthn::decode(&loginparam.client_data_json),
+                webauthn::decode(&loginparam.authenticator_data),
+                webauthn::decode(&loginparam.signature),
+                webauthn::decode(&credential.public_key),
+            ) {
+                (Some(cd), Some(ad), Some(sig), Some(pk)) => (cd, ad, sig, pk),
+                _ => return Err(failed()),
+            };
+            let (origin, rp_id) = (webauthn::rp_origin(), webauthn::rp_id());
+            let assertion = webauthn::verify_assertion(
+                &client_data_json,
+                &authenticator_data,
+                &signature,
+                &public_key,
+                credential.sign_count as u32,
+                &webauthn_ceremony(&ceremony.challenge, &origin, &rp_id),
+            )
+            .map_err(|e| {
+                log::warn!(
+                    target: "security",
+                    "passkey login with credential {} failed: {}",
+                    credential.credential_id,
+                    e
+                );
+                failed()
+            })?;
+            let updated = repository::update_webauthn_sign_count(
+                &credential.credential_id,
+                credential.sign_count,
+                assertion.sign_count as i64,
                 c,
             )?;
-            let mut next = format!(
-                "/authorization?consent_challenge={}",
-                &loginparam.login_challenge
-            );
-            if let Some(s) = &loginparam.state {
-                next = format!("{}&state={}", next, s);
+            // authenticators that don't count leave nothing to race on
+            if updated == 0 && assertion.sign_count != 0 {
+                return Err(failed());
             }
-            Ok(RedirectWithCookie {
-                key: String::from("session_id"),
-                value: session_id,
-                next,
-            })
-        } else {
-            Err(CustomError::ValidationError(Template::render(
-                "login",
-                &LoginContext {
-                    error_msg: Some(String::from("username or password is incorrect")),
-                    login_challenge: loginparam.login_challenge.to_string(),
-                    state: loginparam.state.clone(),
-                },
-            )))
-        }
-    })
-    .await
+            let amr = if assertion.backup_eligible {
+                AMR_SOFTWARE_KEY
+            } else {
+                AMR_HARDWARE_KEY
+            };
+            start_session(
+                &challenge,
+                credential.user_id,
+                &[amr],
+                previous,
+                &config,
+                &acr_policy,
+                c,
+            )
+        })
+        .await?;
+    notify_backchannel_logout(targets, conn);
+    Ok(res)
 }
 
 #[get("/authorization?<consentgetparam..>")]
-async fn get_authorization<'a>(
+async fn get_authorization(
+    session: AuthenticatedSession,
     consentgetparam: Option<ConsentGetParams>,
-    jar: &'a CookieJar<'_>,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
     conn: DBPool,
 ) -> Result<Template, CustomError> {
-    let mut session_id: Option<String> = None;
-    if let Some(session) = jar.get("session_id") {
-        session_id = Some(session.value().to_string());
-    }
+    let config = *config.inner();
+    let browser_csrf_token = jar.get_private("csrf_token").map(|c| c.value().to_string());
     conn.run(move |c| {
-        let mut is_session_error = false;
-        // login check
-        if let Some(id) = session_id {
-            if find_session(&id, c).is_err() {
-                is_session_error = true;
-            }
-        } else {
-            is_session_error = true;
-        }
-        if is_session_error {
-            return Err(CustomError::SessionError);
-        }
         // challenge check
         match consentgetparam {
             Some(param) => {
-                if find_auth_challenge(&param.consent_challenge, c).is_err() {
-                    return Err(CustomError::ChallengeError);
-                }
+                let challenge = find_pending_challenge(
+                    &param.consent_challenge,
+                    &browser_csrf_token,
+                    &config,
+                    c,
+                )?;
+                check_challenge_owner(&challenge, &session)?;
+                let scopes = Scopes::from_str(&challenge.scope).or(Err(CustomError::BadRequest))?;
+                let claims = extra_claims(&challenge.claims_request(), &scopes, session.user_id());
                 Ok(Template::render(
                     "consent",
                     &ConsentContext {
+                        claims,
+                        client_id: challenge.client_id,
+                        scopes: scopes
+                            .scopes
+                            .iter()
+                            .map(|s| ScopeContext {
+                                name: s.to_string(),
+                                description: s.description().to_string(),
+                                claims: s.claims().iter().map(|c| c.to_string()).collect(),
+                                required: s.is_required(),
+                            })
+                            .collect(),
                         consent_challenge: param.consent_challenge,
+                        csrf_token: challenge.csrf_token,
                         state: param.state,
                     },
                 ))
@@ -190,50 +1263,55 @@ async fn get_authorization<'a>(
 }
 
 #[post("/authorization", data = "<consentparam>")]
-async fn post_authorization<'a>(
+async fn post_authorization(
+    session: AuthenticatedSession,
     consentparam: Form<ConsentParams>,
-    jar: &'a CookieJar<'_>,
+    jar: &CookieJar<'_>,
+    config: &State<SessionConfig>,
     conn: DBPool,
 ) -> Result<SuccessfulAuthenticationResponse, CustomError> {
-    let mut session_id: Option<String> = None;
-    if let Some(session) = jar.get("session_id") {
-        session_id = Some(session.value().to_string());
-    }
+    let config = *config.inner();
+    let browser_state = jar.get("browser_state").map(|s| s.value().to_string());
+    let browser_csrf_token = jar.get_private("csrf_token").map(|c| c.value().to_string());
     conn.run(move |c| {
-        let mut is_session_error = false;
-        // login check
-        if let Some(id) = session_id {
-            if find_session(&id, c).is_err() {
-                is_session_error = false;
-            }
-        } else {
-            is_session_error = false;
-        }
-        if is_session_error {
-            return Err(CustomError::SessionError);
-        }
         // challenge check
-        let challenge = find_auth_challenge(&consentparam.consent_challenge, c);
-        if challenge.is_err() {
-            return Err(CustomError::ChallengeError);
+        let mut challenge = find_pending_challenge(
+            &consentparam.consent_challenge,
+            &browser_csrf_token,
+            &config,
+            c,
+        )?;
+        check_challenge_owner(&challenge, &session)?;
+        check_csrf_token(&challenge, &consentparam.csrf_token)?;
+        if !consentparam.is_accepted() {
+            return Err(challenge_error(
+                &challenge,
+                AuthorizationError::AccessDenied,
+                c,
+            )?);
         }
-        let challenge = challenge.unwrap();
-        let auth_code = generate_challenge();
-        create_auth_code(
-            AuthCode {
-                code: auth_code.clone(),
+        let user_id = session.user_id().to_string();
+        let requested = Scopes::from_str(&challenge.scope).or(Err(CustomError::BadRequest))?;
+        let granted = consentparam.granted_scopes(&requested);
+        // claims of the scopes the end-user deselected aren't released through
+        // the claims parameter either
+        if let Some(mut claims) = challenge.claims_request() {
+            let (requested_claims, granted_claims) = (requested.claims(), granted.claims());
+            claims
+                .retain(|name| granted_claims.contains(&name) || !requested_claims.contains(&name));
+            challenge.claims = Some(claims.to_string());
+        }
+        challenge.scope = granted.to_string();
+        // remember the consent so that the next authentication request can skip it
+        save_grant(
+            NewGrant {
+                user_id,
                 client_id: challenge.client_id.clone(),
-                user_id: String::from("userid"), // dummy user id
                 scope: challenge.scope.clone(),
-                nonce: challenge.nonce.unwrap_or("".to_string()),
             },
             c,
         )?;
-        Ok(SuccessfulAuthenticationResponse::new(
-            &challenge.redirect_uri,
-            &auth_code,
-            &consentparam.state,
-        ))
+        issue_authorization_response(&challenge, &browser_state, c)
     })
     .await
 }
@@ -241,45 +1319,56 @@ async fn post_authorization<'a>(
 #[post("/token", data = "<tokenparam>")]
 async fn post_token(
     tokenparam: Form<TokenRequest>,
-    basic: Basic,
+    credentials: ClientCredentials,
+    dpop: DpopProof,
+    dpop_state: &State<DpopState>,
     conn: DBPool,
 ) -> Result<Json<SuccessfulTokenResponse>, CustomError> {
+    // a token requested with a DPoP proof is bound to the proof's key
+    let dpop_jkt = match dpop.proof {
+        Some(_) => Some(dpop_state.verify(&dpop, None)?),
+        None => None,
+    };
     conn.run(move |c| {
         // check auth code
         let auth_code = repository::find_auth_code(tokenparam.code(), c)?;
         let client = repository::find_client(&auth_code.client_id, c)?;
         // check client credential
-        if basic.client_id != client.client_id || basic.client_secret != client.client_secret {
+        if !credentials.authenticate(&client, tokenparam.client_id()) {
             return Err(CustomError::UnauthorizedError);
         }
-        let access_token = generate_challenge();
-        repository::create_token(
-            NewToken {
-                access_token: access_token.clone(),
-                user_id: auth_code.user_id,
-                scope: auth_code.scope,
-            },
-            c,
-        )?;
-        let now = Utc::now();
-        let exp = now + Duration::hours(12);
-        let claim = IdToken {
-            iss: "http://example.com".to_string(),
-            sub: "userid".to_string(), // dummy id
-            aud: auth_code.client_id,
-            exp: exp.timestamp() as usize,
-            iat: now.timestamp() as usize,
-            nonce: auth_code.nonce,
+        let cnf = Confirmation {
+            jkt: dpop_jkt.clone(),
+            // clients that registered for it get tokens bound to their certificate
+            x5t_s256: credentials
+                .certificate
+                .as_ref()
+                .filter(|_| client.tls_client_certificate_bound_access_tokens)
+                .map(mtls::thumbprint),
         };
-        let jwt_header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
-        let id_token = jsonwebtoken::encode(
-            &jwt_header,
-            &claim,
-            &jsonwebtoken::EncodingKey::from_rsa_pem(include_bytes!("private-key.pem")).unwrap(),
+        let access_token = issue_access_token(
+            &auth_code.user_id,
+            &client,
+            &auth_code.scope,
+            &auth_code.claims,
+            cnf,
+            c,
         )?;
+        let mut claim = id_token_claims(
+            &client,
+            &auth_code.user_id,
+            &auth_code.nonce,
+            auth_code.auth_time,
+            auth_code.max_age,
+            &auth_code.sid,
+            &auth_code.claims_request(),
+        );
+        claim.amr = amr_claim(&auth_code.amr);
+        claim.acr = auth_code.acr.clone();
+        let id_token = sign_jwt(&claim)?;
         Ok(Json(SuccessfulTokenResponse {
             access_token,
-            token_type: "Bearer".to_string(),
+            token_type: if dpop_jkt.is_some() { "DPoP" } else { "Bearer" }.to_string(),
             refresh_token: None,
             expires_in: 3600,
             id_token,
@@ -288,62 +1377,654 @@ async fn post_token(
     .await
 }
 
+/// Token introspection for resource servers, which have to authenticate as clients
+/// https://www.rfc-editor.org/rfc/rfc7662.html
+#[post("/introspect", data = "<introspectparam>")]
+async fn post_introspect(
+    introspectparam: Form<IntrospectionRequest>,
+    credentials: ClientCredentials,
+    conn: DBPool,
+) -> Result<Json<IntrospectionResponse>, CustomError> {
+    conn.run(move |c| {
+        let client_id = credentials
+            .client_id(introspectparam.client_id.as_deref())
+            .unwrap_or_default();
+        let client =
+            repository::find_client(client_id, c).or(Err(CustomError::UnauthorizedError))?;
+        if !credentials.authenticate(&client, introspectparam.client_id.as_deref()) {
+            return Err(CustomError::UnauthorizedError);
+        }
+        let token = match repository::find_token(&access_token_id(&introspectparam.token), c) {
+            Ok(token) if token.is_valid() => token,
+            Ok(_) | Err(NotFound) => return Ok(Json(IntrospectionResponse::default())),
+            Err(e) => return Err(e.into()),
+        };
+        let cnf = token.cnf();
+        Ok(Json(IntrospectionResponse {
+            active: true,
+            token_type: Some(if cnf.jkt.is_some() { "DPoP" } else { "Bearer" }.to_string()),
+            exp: Some(token.expires_at().timestamp()),
+            iat: Some(token.created_at.timestamp()),
+            cnf: Some(cnf).filter(|c| !c.is_empty()),
+            scope: Some(token.scope),
+            client_id: Some(token.client_id),
+            sub: Some(token.user_id),
+        }))
+    })
+    .await
+}
+
 #[get("/userinfo")]
 async fn get_userinfo(
     inforeq: UserinfoRequest,
+    certificate: ClientCertificate,
+    dpop: DpopProof,
+    dpop_state: &State<DpopState>,
     conn: DBPool,
 ) -> Result<Json<SuccessfulUserinfoResponse>, CustomError> {
+    let presented = Confirmation {
+        jkt: if inforeq.dpop {
+            Some(
+                dpop_state
+                    .verify(&dpop, Some(&inforeq.bearer))
+                    .map_err(|e| e.at_resource())?,
+            )
+        } else {
+            None
+        },
+        x5t_s256: certificate.certificate.as_ref().map(mtls::thumbprint),
+    };
     conn.run(move |c| {
-        let token = repository::find_token(&inforeq.bearer, c)?;
-        if token.is_valid() && token.access_token == inforeq.bearer {
+        let token_id = access_token_id(&inforeq.bearer);
+        let token = repository::find_token(&token_id, c)?;
+        // a bound token is only accepted from whoever holds its keys
+        if token.is_valid() && token.access_token == token_id && token.is_bound_to(&presented) {
             let scopes = Scopes::from_str(&token.scope).unwrap();
-            let default_address = Address {
-                formatted: "".to_string(),
-                street_address: "".to_string(),
-                locality: "".to_string(),
-                region: "".to_string(),
-                postal_code: "".to_string(),
-                country: "".to_string(),
-            };
-            let mut res = SuccessfulUserinfoResponse {
-                sub: "userid".to_string(),
-                name: "tarou tanaka".to_string(),
-                email: "".to_string(),
-                email_verified: false,
-                address: default_address,
-                phone_number: "".to_string(),
-                phone_number_verified: false,
-            };
-            for s in scopes.scopes.iter() {
-                match s {
-                    &Scope::Phone => {
-                        res.phone_number = String::from("111-1234-5678");
-                        res.phone_number_verified = true;
-                    }
-                    &Scope::Email => {
-                        res.email = String::from("test@example.com");
-                        res.email_verified = true;
-                    }
-                    &Scope::Address => {
-                        res.address = Address {
-                            formatted: "formatted address".to_string(),
-                            street_address: "street address".to_string(),
-                            locality: "locality".to_string(),
-                            region: "region".to_string(),
-                            postal_code: "postal code".to_string(),
-                            country: "country".to_string(),
-                        };
-                    }
-                    _ => {}
-                }
+            let available = user_claims(&token.user_id);
+            // the granted scopes' claims, plus those requested for userinfo
+            let scope_claims = scopes.claims();
+            let mut claims: Claims = available
+                .iter()
+                .filter(|(name, _)| scope_claims.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            if let Some(requested) = token.claims_request() {
+                claims.extend(requested.userinfo_claims(&available));
             }
-            return Ok(Json(res));
+            return Ok(Json(SuccessfulUserinfoResponse {
+                sub: token.user_id,
+                claims,
+            }));
         }
         Err(CustomError::UnauthorizedError)
     })
     .await
 }
 
+#[get("/account")]
+async fn get_account(session: AuthenticatedSession, conn: DBPool) -> Result<Template, CustomError> {
+    let session = session.session;
+    conn.run(move |c| {
+        let sessions = repository::find_sessions_by_user(&session.user_id, c)?;
+        let grants = repository::find_grants_by_user(&session.user_id, c)?;
+        let mfa_enabled = mfa::is_enrolled(&session.user_id, c)?;
+        Ok(Template::render(
+            "account",
+            &AccountContext {
+                sessions: sessions
+                    .into_iter()
+                    .map(|s| SessionContext {
+                        auth_time: s.auth_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                        current: s.session_id == session.session_id,
+                    })
+                    .collect(),
+                grants: grants
+                    .into_iter()
+                    .map(|g| GrantContext {
+                        client_id: g.client_id,
+                        scopes: g.scope.split_whitespace().map(|s| s.to_string()).collect(),
+                        granted_at: g.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    })
+                    .collect(),
+                mfa_enabled,
+                csrf_token: session.csrf_token,
+            },
+        ))
+    })
+    .await
+}
+
+fn totp_enrollment(
+    credential: &TotpCredential,
+    csrf_token: &str,
+    error_msg: Option<&str>,
+) -> Template {
+    Template::render(
+        "totp",
+        &TotpContext {
+            enabled: false,
+            secret: Some(credential.secret.clone()),
+            provisioning_uri: Some(mfa::provisioning_uri(
+                &credential.secret,
+                &credential.user_id,
+            )),
+            recovery_codes_left: 0,
+            error_msg: error_msg.map(String::from),
+            csrf_token: csrf_token.to_string(),
+        },
+    )
+}
+
+#[get("/account/mfa")]
+async fn get_account_mfa(
+    session: AuthenticatedSession,
+    conn: DBPool,
+) -> Result<Template, CustomError> {
+    let session = session.session;
+    conn.run(move |c| {
+        match repository::find_totp_credential(&session.user_id, c) {
+            Ok(credential) if credential.confirmed => Ok(Template::render(
+                "totp",
+                &TotpContext {
+                    enabled: true,
+                    secret: None,
+                    provisioning_uri: None,
+                    recovery_codes_left: repository::count_recovery_codes(&session.user_id, c)?,
+                    error_msg: None,
+                    csrf_token: session.csrf_token,
+                },
+            )),
+            // keep offering the same secret until the user confirms it
+            Ok(credential) => Ok(totp_enrollment(&credential, &session.csrf_token, None)),
+            Err(NotFound) => Ok(Template::render(
+                "totp",
+                &TotpContext {
+                    enabled: false,
+                    secret: None,
+                    provisioning_uri: None,
+                    recovery_codes_left: 0,
+                    error_msg: None,
+                    csrf_token: session.csrf_token,
+                },
+            )),
+            Err(e) => Err(e.into()),
+        }
+    })
+    .await
+}
+
+/// Creates the secret the enrollment page offers, unless one is already pending
+#[post("/account/mfa/setup", data = "<setupparam>")]
+async fn post_account_mfa_setup(
+    session: AuthenticatedSession,
+    setupparam: Form<SetupTotpParams>,
+    conn: DBPool,
+) -> Result<Redirect, CustomError> {
+    session.check_csrf_token(&setupparam.csrf_token)?;
+    let user_id = session.user_id().to_string();
+    conn.run(move |c| {
+        match repository::find_totp_credential(&user_id, c) {
+            Ok(credential) if credential.confirmed => return Err(CustomError::BadRequest),
+            Ok(_) => {}
+            Err(NotFound) => {
+                repository::save_totp_credential(
+                    TotpCredential {
+                        user_id: user_id.clone(),
+                        secret: mfa::generate_secret(),
+                        confirmed: false,
+                        last_used_step: None,
+                        created_at: Utc::now().naive_utc(),
+                    },
+                    c,
+                )?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(Redirect::to("/account/mfa"))
+    })
+    .await
+}
+
+/// Confirms the enrollment with a code from the authenticator and hands out
+/// the recovery codes, which are shown this one time only
+#[post("/account/mfa", data = "<confirmparam>")]
+async fn post_account_mfa(
+    session: AuthenticatedSession,
+    confirmparam: Form<ConfirmTotpParams>,
+    conn: DBPool,
+) -> Result<Template, CustomError> {
+    session.check_csrf_token(&confirmparam.csrf_token)?;
+    let user_id = session.user_id().to_string();
+    conn.run(move |c| {
+        let mut credential = repository::find_totp_credential(&user_id, c)?;
+        if credential.confirmed {
+            return Err(CustomError::BadRequest);
+        }
+        let step = match mfa::verify_totp(
+            &credential.secret,
+            &confirmparam.code,
+            Utc::now().timestamp(),
+            None,
+        ) {
+            Some(step) => step,
+            None => {
+                return Err(CustomError::ValidationError(totp_enrollment(
+                    &credential,
+                    &confirmparam.csrf_token,
+                    Some("the code is incorrect"),
+                )))
+            }
+        };
+        credential.confirmed = true;
+        credential.last_used_step = Some(step);
+        repository::save_totp_credential(credential, c)?;
+        let codes = mfa::generate_recovery_codes();
+        repository::save_recovery_codes(
+            &user_id,
+            codes
+                .iter()
+                .map(|code| RecoveryCode {
+                    user_id: user_id.clone(),
+                    code_hash: mfa::hash_recovery_code(code),
+                })
+                .collect(),
+            c,
+        )?;
+        Ok(Template::render(
+            "recovery_codes",
+            &RecoveryCodesContext { codes },
+        ))
+    })
+    .await
+}
+
+/// Lists the user's passkeys and starts the registration of another one
+fn webauthn_page(
+    session: &Session,
+    error_msg: Option<&str>,
+    conn: &MysqlConnection,
+) -> Result<Template, CustomError> {
+    let user_id = session.user_id.as_str();
+    let credentials = repository::find_webauthn_credentials_by_user(user_id, conn)?;
+    let challenge = webauthn::new_challenge();
+    repository::create_webauthn_challenge(
+        WebAuthnChallenge {
+            challenge: challenge.clone(),
+            user_id: Some(user_id.to_string()),
+            login_challenge: None,
+            created_at: Utc::now().naive_utc(),
+        },
+        conn,
+    )?;
+    Ok(Template::render(
+        "webauthn",
+        &WebAuthnContext {
+            creation_options: CreationOptions::new(
+                &challenge,
+                user_id,
+                credentials
+                    .iter()
+                    .map(|c| c.credential_id.clone())
+                    .collect(),
+            ),
+            credentials: credentials
+                .into_iter()
+                .map(|c| WebAuthnCredentialContext {
+                    credential_id: c.credential_id,
+                    synced: c.backup_eligible,
+                    created_at: c.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    last_used_at: c
+                        .last_used_at
+                        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()),
+                })
+                .collect(),
+            error_msg: error_msg.map(String::from),
+            csrf_token: session.csrf_token.clone(),
+        },
+    ))
+}
+
+#[get("/account/webauthn")]
+async fn get_account_webauthn(
+    session: AuthenticatedSession,
+    conn: DBPool,
+) -> Result<Template, CustomError> {
+    let session = session.session;
+    conn.run(move |c| webauthn_page(&session, None, c)).await
+}
+
+#[post("/account/webauthn", data = "<registerparam>")]
+async fn post_account_webauthn(
+    session: AuthenticatedSession,
+    registerparam: Form<RegisterCredentialParams>,
+    config: &State<SessionConfig>,
+    conn: DBPool,
+) -> Result<Redirect, CustomError> {
+    let config = *config.inner();
+    let session = session.session;
+    conn.run(move |c| {
+        let user_id = session.user_id.clone();
+        let ceremony = take_webauthn_challenge(&registerparam.challenge, &config, c)?;
+        if ceremony.user_id.as_deref() != Some(user_id.as_str()) {
+            return Err(CustomError::ChallengeError);
+        }
+        let failed = |error_msg: &str| match webauthn_page(&session, Some(error_msg), c) {
+            Ok(page) => CustomError::ValidationError(page),
+            Err(e) => e,
+        };
+        let (client_data_json, attestation_object) = match (
+            webauthn::decode(&registerparam.client_data_json),
+            webauthn::decode(&registerparam.attestation_object),
+        ) {
+            (Some(cd), Some(ao)) => (cd, ao),
+            _ => return Err(failed("the passkey couldn't be registered")),
+        };
+        let (origin, rp_id) = (webauthn::rp_origin(), webauthn::rp_id());
+        let registered = webauthn::verify_registration(
+            &client_data_json,
+            &attestation_object,
+            &webauthn_ceremony(&ceremony.challenge, &origin, &rp_id),
+        )
+        .map_err(|e| {
+            log::warn!(
+                target: "security",
+                "passkey registration for {} failed: {}",
+                user_id,
+                e
+            );
+            failed("the passkey couldn't be registered")
+        })?;
+        let credential_id = webauthn::encode(&registered.id);
+        match repository::find_webauthn_credential(&credential_id, c) {
+            Ok(_) => return Err(failed("the passkey is already registered")),
+            Err(NotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+        repository::create_webauthn_credential(
+            WebAuthnCredential {
+                credential_id,
+                user_id: user_id.clone(),
+                public_key: webauthn::encode(&registered.public_key),
+                sign_count: registered.sign_count as i64,
+                backup_eligible: registered.backup_eligible,
+                created_at: Utc::now().naive_utc(),
+                last_used_at: None,
+            },
+            c,
+        )?;
+        Ok(Redirect::to("/account/webauthn"))
+    })
+    .await
+}
+
+#[post("/account/webauthn/delete", data = "<deleteparam>")]
+async fn post_delete_webauthn(
+    session: AuthenticatedSession,
+    deleteparam: Form<DeleteCredentialParams>,
+    conn: DBPool,
+) -> Result<Redirect, CustomError> {
+    session.check_csrf_token(&deleteparam.csrf_token)?;
+    let user_id = session.user_id().to_string();
+    conn.run(move |c| {
+        repository::delete_webauthn_credential(&user_id, &deleteparam.credential_id, c)?;
+        Ok(Redirect::to("/account/webauthn"))
+    })
+    .await
+}
+
+#[post("/account/grants/revoke", data = "<revokeparam>")]
+async fn post_revoke_grant(
+    session: AuthenticatedSession,
+    revokeparam: Form<RevokeGrantParams>,
+    conn: DBPool,
+) -> Result<Redirect, CustomError> {
+    session.check_csrf_token(&revokeparam.csrf_token)?;
+    let session = session.session;
+    conn.run(move |c| {
+        // revoking a grant also invalidates everything issued under it
+        repository::delete_grant(&session.user_id, &revokeparam.client_id, c)?;
+        repository::delete_tokens_by_client(&session.user_id, &revokeparam.client_id, c)?;
+        repository::delete_auth_codes_by_client(&session.user_id, &revokeparam.client_id, c)?;
+        Ok(Redirect::to("/account"))
+    })
+    .await
+}
+
+#[post("/account/sessions/logout_others", data = "<logoutparam>")]
+async fn post_logout_other_sessions(
+    session: AuthenticatedSession,
+    logoutparam: Form<LogoutOtherSessionsParams>,
+    conn: DBPool,
+) -> Result<Redirect, CustomError> {
+    session.check_csrf_token(&logoutparam.csrf_token)?;
+    let session = session.session;
+    let targets = conn
+        .run(move |c| {
+            let mut targets = vec![];
+            for other in repository::find_sessions_by_user(&session.user_id, c)? {
+                if other.session_id != session.session_id {
+                    // other browsers can't load front-channel iframes for us
+                    targets.append(&mut end_session(other, c)?.backchannel);
+                }
+            }
+            Ok::<_, CustomError>(targets)
+        })
+        .await?;
+    notify_backchannel_logout(targets, conn);
+    Ok(Redirect::to("/account"))
+}
+
+/// The client the post_logout_redirect_uri has to be registered for. An
+/// id_token_hint must have been issued to that client.
+fn logout_client_id(
+    client_id: &Option<String>,
+    hint: &Option<IdToken>,
+) -> Result<Option<String>, &'static str> {
+    match (client_id, hint) {
+        (Some(id), Some(h)) if id != &h.aud => Err("client_id doesn't match id_token_hint."),
+        (Some(id), _) => Ok(Some(id.clone())),
+        (None, Some(h)) => Ok(Some(h.aud.clone())),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Validates an RP-initiated logout request. Returns the end-user the
+/// id_token_hint was issued to and where to redirect after logout.
+fn validate_end_session(
+    param: &EndSessionParams,
+    conn: &MysqlConnection,
+) -> Result<(Option<String>, Option<String>), CustomError> {
+    let invalid = |msg: &str| {
+        CustomError::ValidationError(Template::render(
+            "error",
+            &ErrorContext {
+                error_msg: msg.to_string(),
+            },
+        ))
+    };
+    let hint = match &param.id_token_hint {
+        Some(t) => Some(verify_jwt::<IdToken>(t).or(Err(invalid("id_token_hint is invalid.")))?),
+        None => None,
+    };
+    let client_id = logout_client_id(&param.client_id, &hint).map_err(invalid)?;
+    let next = match &param.post_logout_redirect_uri {
+        Some(uri) => {
+            let client = client_id
+                .and_then(|id| repository::find_client(&id, conn).ok())
+                .ok_or(invalid("client is unknown."))?;
+            client
+                .check_post_logout_redirect_uri(uri)
+                .or(Err(invalid("post_logout_redirect_uri is not registered.")))?;
+            Some(
+                RedirectBuilder::new(uri)
+                    .param_opt("state", &param.state)
+                    .query(),
+            )
+        }
+        None => None,
+    };
+    Ok((hint.map(|h| h.sub), next))
+}
+
+/// Relying parties to notify once a session has ended
+#[derive(Default)]
+struct EndedSession {
+    backchannel: Vec<LogoutTarget>,
+    frontchannel: Vec<String>,
+}
+
+/// Builds the logout tokens and front-channel logout URIs for every relying
+/// party that took part in the session
+fn logout_notifications(
+    session: &Session,
+    conn: &MysqlConnection,
+) -> Result<EndedSession, CustomError> {
+    let mut ended = EndedSession::default();
+    for session_client in repository::find_session_clients(&session.sid, conn)? {
+        let client = repository::find_client(&session_client.client_id, conn)?;
+        if let Some(uri) = &client.frontchannel_logout_uri {
+            let mut builder = RedirectBuilder::new(uri);
+            if client.frontchannel_logout_session_required {
+                builder = builder.param("iss", ISSUER).param("sid", &session.sid);
+            }
+            ended.frontchannel.push(builder.query());
+        }
+        if let Some(uri) = client.backchannel_logout_uri {
+            let claim = LogoutToken::new(&session.user_id, &client.client_id, &session.sid);
+            ended.backchannel.push(LogoutTarget {
+                sid: session.sid.clone(),
+                client_id: client.client_id,
+                uri,
+                logout_token: sign_jwt_with_type(&claim, "logout+jwt")?,
+            });
+        }
+    }
+    Ok(ended)
+}
+
+/// Deletes the session and returns the relying parties to be notified
+fn end_session(session: Session, conn: &MysqlConnection) -> Result<EndedSession, CustomError> {
+    let ended = logout_notifications(&session, conn)?;
+    repository::delete_session_clients(&session.sid, conn)?;
+    repository::delete_session(session.session_id, conn)?;
+    Ok(ended)
+}
+
+/// Notifies relying parties in the background so that slow ones don't block the logout
+fn notify_backchannel_logout(targets: Vec<LogoutTarget>, conn: DBPool) {
+    if targets.is_empty() {
+        return;
+    }
+    rocket::tokio::spawn(async move {
+        let http = reqwest::Client::new();
+        for target in targets {
+            let deliveries =
+                backchannel::deliver(&http, &target, std::time::Duration::from_secs(1)).await;
+            let res = conn
+                .run(move |c| {
+                    for delivery in deliveries {
+                        repository::create_logout_delivery(delivery, c)?;
+                    }
+                    Ok::<(), diesel::result::Error>(())
+                })
+                .await;
+            if let Err(e) = res {
+                log::error!("failed to record back-channel logout deliveries: {}", e);
+            }
+        }
+    });
+}
+
+/// Redirects straight to `next` unless relying parties have to be logged out
+/// through the browser first
+fn logged_out(next: Option<String>, frontchannel: Vec<String>) -> EndSessionResponse {
+    match next {
+        Some(n) if frontchannel.is_empty() => EndSessionResponse::Redirect(Redirect::to(n)),
+        next => EndSessionResponse::LoggedOut(Template::render(
+            "logged_out",
+            &LoggedOutContext {
+                frontchannel_logout_uris: frontchannel,
+                next,
+            },
+        )),
+    }
+}
+
+/// Logs the end-user out. The logout page asks for confirmation first unless
+/// the request was `confirmed` there or the RP proved who is logged in.
+async fn process_end_session(
+    endsessionparam: EndSessionParams,
+    session: Option<AuthenticatedSession>,
+    confirmed: bool,
+    jar: &CookieJar<'_>,
+    conn: DBPool,
+) -> Result<EndSessionResponse, CustomError> {
+    let (res, targets) = conn
+        .run(move |c| {
+            let (sub, next) = validate_end_session(&endsessionparam, c)?;
+            match session.map(|s| s.session) {
+                Some(s) if confirmed || sub.as_deref() == Some(s.user_id.as_str()) => {
+                    let ended = end_session(s, c)?;
+                    Ok::<_, CustomError>((logged_out(next, ended.frontchannel), ended.backchannel))
+                }
+                Some(s) => Ok((
+                    EndSessionResponse::Confirm(Template::render(
+                        "logout",
+                        &LogoutContext {
+                            id_token_hint: endsessionparam.id_token_hint,
+                            post_logout_redirect_uri: endsessionparam.post_logout_redirect_uri,
+                            client_id: endsessionparam.client_id,
+                            state: endsessionparam.state,
+                            csrf_token: s.csrf_token,
+                        },
+                    )),
+                    vec![],
+                )),
+                None => Ok((logged_out(next, vec![]), vec![])),
+            }
+        })
+        .await?;
+    if !matches!(res, EndSessionResponse::Confirm(_)) {
+        jar.remove_private(Cookie::named("session_id"));
+        jar.remove(Cookie::named("browser_state"));
+    }
+    notify_backchannel_logout(targets, conn);
+    Ok(res)
+}
+
+#[get("/end_session?<endsessionparam..>")]
+async fn get_end_session(
+    endsessionparam: EndSessionParams,
+    session: Option<AuthenticatedSession>,
+    jar: &CookieJar<'_>,
+    conn: DBPool,
+) -> Result<EndSessionResponse, CustomError> {
+    process_end_session(endsessionparam, session, false, jar, conn).await
+}
+
+/// Accepts both the logout page's confirmation, which carries the session's
+/// CSRF token, and logout requests RPs send with POST
+#[post("/end_session", data = "<endsessionparam>")]
+async fn post_end_session(
+    endsessionparam: Form<EndSessionParams>,
+    session: Option<AuthenticatedSession>,
+    jar: &CookieJar<'_>,
+    conn: DBPool,
+) -> Result<EndSessionResponse, CustomError> {
+    let endsessionparam = endsessionparam.into_inner();
+    let confirmed = match (&session, &endsessionparam.csrf_token) {
+        (Some(s), Some(csrf_token)) => s.check_csrf_token(csrf_token).is_ok(),
+        _ => false,
+    };
+    process_end_session(endsessionparam, session, confirmed, jar, conn).await
+}
+
+/// Renders the login-required page when the AuthenticatedSession guard fails
+#[catch(401)]
+fn session_error() -> CustomError {
+    CustomError::SessionError
+}
+
 #[launch]
 pub fn run() -> _ {
     let db: Map<_, Value> = map! {
@@ -356,15 +2037,472 @@ pub fn run() -> _ {
             "/",
             routes![
                 index,
+                get_configuration,
+                get_check_session,
                 get_client,
+                post_par,
                 get_authenticate,
                 post_authenticate,
+                get_mfa,
+                post_mfa,
+                get_webauthn_login,
+                post_webauthn_login,
                 get_authorization,
                 post_authorization,
                 post_token,
+                post_introspect,
                 get_userinfo,
+                get_account,
+                get_account_mfa,
+                post_account_mfa,
+                post_account_mfa_setup,
+                get_account_webauthn,
+                post_account_webauthn,
+                post_delete_webauthn,
+                post_revoke_grant,
+                post_logout_other_sessions,
+                get_end_session,
+                post_end_session,
             ],
         )
         .attach(DBPool::fairing())
         .attach(Template::fairing())
+        .register("/authorization", catchers![session_error])
+        .register("/account", catchers![session_error])
+        .attach(AdHoc::config::<SessionConfig>())
+        .attach(AdHoc::config::<AcrPolicy>())
+        .attach(AdHoc::config::<MtlsConfig>())
+        .attach(AdHoc::config::<ProxyConfig>())
+        .manage(DpopState::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::{
+        http::{ContentType, Status},
+        local::blocking::Client,
+        route::StaticInfo,
+        Route,
+    };
+
+    /// Rebuilds the route without its sentinels, which would refuse to launch
+    /// without a database
+    fn without_db(info: StaticInfo) -> Route {
+        Route::new(info.method, info.uri, info.handler)
+    }
+
+    /// Mounts the consent routes without a database: the session guard has to
+    /// reject the request before anything touches it
+    fn consent_client() -> Client {
+        let rocket = rocket::build()
+            .mount(
+                "/",
+                vec![
+                    without_db(get_authorization {}.into_info()),
+                    without_db(post_authorization {}.into_info()),
+                ],
+            )
+            .register("/authorization", catchers![session_error])
+            .attach(Template::fairing());
+        Client::tracked(rocket).expect("valid rocket instance")
+    }
+
+    #[test]
+    fn post_authorization_without_session_is_rejected() {
+        let client = consent_client();
+        let res = client
+            .post("/authorization")
+            .header(ContentType::Form)
+            .body("consent=ok&consent_challenge=challenge&scope=openid")
+            .dispatch();
+        assert_eq!(Status::Unauthorized, res.status());
+        assert!(res.headers().get_one("Location").is_none());
+    }
+
+    #[test]
+    fn post_authorization_with_forged_session_is_rejected() {
+        let client = consent_client();
+        // a plain cookie isn't accepted where an encrypted one is expected
+        let res = client
+            .post("/authorization")
+            .header(ContentType::Form)
+            .cookie(Cookie::new("session_id", "forged"))
+            .body("consent=ok&consent_challenge=challenge&scope=openid")
+            .dispatch();
+        assert_eq!(Status::Unauthorized, res.status());
+        assert!(res.headers().get_one("Location").is_none());
+    }
+
+    #[test]
+    fn get_authorization_without_session_is_rejected() {
+        let client = consent_client();
+        let res = client
+            .get("/authorization?consent_challenge=challenge")
+            .dispatch();
+        assert_eq!(Status::Unauthorized, res.status());
+    }
+
+    #[test]
+    fn authenticated_session_reports_server_errors() {
+        let rocket = rocket::build().mount("/", vec![without_db(get_account {}.into_info())]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+        let res = client
+            .get("/account")
+            .private_cookie(Cookie::new("session_id", "session"))
+            .dispatch();
+        assert_eq!(Status::InternalServerError, res.status());
+
+        let rocket = rocket::build()
+            .mount("/", vec![without_db(get_account {}.into_info())])
+            .attach(AdHoc::config::<SessionConfig>());
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+        let res = client
+            .get("/account")
+            .private_cookie(Cookie::new("session_id", "session"))
+            .dispatch();
+        assert_eq!(Status::ServiceUnavailable, res.status());
+    }
+
+    #[test]
+    fn post_par_without_client_authentication_is_rejected() {
+        let rocket = rocket::build().mount("/", vec![without_db(post_par {}.into_info())]);
+        let client = Client::tracked(rocket).expect("valid rocket instance");
+        let res = client
+            .post("/par")
+            .header(ContentType::Form)
+            .body("client_id=client&response_type=code&scope=openid")
+            .dispatch();
+        assert_eq!(Status::Unauthorized, res.status());
+    }
+
+    #[test]
+    fn access_token_id_ok() {
+        let claims = AccessTokenClaims {
+            iss: ISSUER.to_string(),
+            sub: "user".to_string(),
+            aud: format!("{}/userinfo", ISSUER),
+            client_id: "client".to_string(),
+            scope: "openid".to_string(),
+            jti: "token-id".to_string(),
+            exp: Utc::now().timestamp() + 3600,
+            iat: Utc::now().timestamp(),
+            cnf: Confirmation::default(),
+        };
+        let jwt = sign_jwt_with_type(&claims, "at+jwt").unwrap();
+        assert_eq!("token-id", access_token_id(&jwt));
+        // other JWTs of this provider aren't access tokens
+        let jwt = sign_jwt_with_type(&claims, "logout+jwt").unwrap();
+        assert_eq!(jwt, access_token_id(&jwt));
+        assert_eq!("opaque", access_token_id("opaque"));
+    }
+
+    fn id_token_hint(aud: &str) -> IdToken {
+        IdToken {
+            iss: ISSUER.to_string(),
+            sub: "user".to_string(),
+            aud: aud.to_string(),
+            exp: 0,
+            iat: 0,
+            nonce: String::default(),
+            auth_time: None,
+            at_hash: None,
+            c_hash: None,
+            sid: None,
+            amr: None,
+            acr: None,
+            claims: Claims::default(),
+        }
+    }
+
+    #[test]
+    fn logout_client_id_ok() {
+        let hint = Some(id_token_hint("client"));
+        assert_eq!(
+            Ok(Some("client".to_string())),
+            logout_client_id(&Some("client".to_string()), &hint)
+        );
+        assert_eq!(
+            Ok(Some("client".to_string())),
+            logout_client_id(&None, &hint)
+        );
+        assert_eq!(
+            Ok(Some("client".to_string())),
+            logout_client_id(&Some("client".to_string()), &None)
+        );
+        assert_eq!(Ok(None), logout_client_id(&None, &None));
+    }
+
+    #[test]
+    fn logout_client_id_ng() {
+        // the hint was issued to another client than the one redirected to
+        let hint = Some(id_token_hint("other"));
+        assert!(logout_client_id(&Some("client".to_string()), &hint).is_err());
+    }
+
+    #[test]
+    fn id_token_hint_from_another_issuer_is_rejected() {
+        let mut hint = id_token_hint("client");
+        hint.iss = "https://other.example.com".to_string();
+        let jwt = sign_jwt(&hint).unwrap();
+        assert!(verify_jwt::<IdToken>(&jwt).is_err());
+        hint.iss = ISSUER.to_string();
+        let jwt = sign_jwt(&hint).unwrap();
+        assert_eq!("client", verify_jwt::<IdToken>(&jwt).unwrap().aud);
+    }
+
+    /// Launches the whole server against the database configured in Rocket.toml
+    /// and connects to it to set up fixtures
+    fn sso_client() -> (Client, MysqlConnection) {
+        let client = Client::tracked(run()).expect("valid rocket instance");
+        let url: String = client
+            .rocket()
+            .figment()
+            .extract_inner("databases.oidc_db.url")
+            .expect("database url");
+        let conn = MysqlConnection::establish(&url).expect("database connection");
+        (client, conn)
+    }
+
+    /// Registers a code flow client and logs its end-user in, returning the
+    /// client_id and the session id
+    fn sso_fixture(
+        auth_time: NaiveDateTime,
+        granted: bool,
+        conn: &MysqlConnection,
+    ) -> (String, String) {
+        let client_id = generate_challenge();
+        create_client(
+            crate::models::Client {
+                client_id: client_id.clone(),
+                ..crate::models::Client::for_test()
+            },
+            conn,
+        )
+        .unwrap();
+        let session_id = generate_challenge();
+        let user_id = generate_challenge();
+        create_session(
+            Session {
+                session_id: session_id.clone(),
+                user_id: user_id.clone(),
+                auth_time,
+                sid: generate_challenge(),
+                expires_at: Utc::now().naive_utc() + Duration::hours(1),
+                last_seen_at: Utc::now().naive_utc(),
+                amr: String::from("pwd"),
+                acr: String::default(),
+                csrf_token: generate_challenge(),
+            },
+            conn,
+        )
+        .unwrap();
+        if granted {
+            save_grant(
+                NewGrant {
+                    user_id,
+                    client_id: client_id.clone(),
+                    scope: String::from("openid"),
+                },
+                conn,
+            )
+            .unwrap();
+        }
+        (client_id, session_id)
+    }
+
+    const CALLBACK: &str = "https://client.example.com/cb";
+
+    fn sso_request(
+        client: &Client,
+        client_id: &str,
+        session_id: &str,
+        redirect_uri: &str,
+        max_age: Option<u64>,
+    ) -> (Status, Option<String>) {
+        let uri = RedirectBuilder::new("/authenticate")
+            .param("client_id", client_id)
+            .param("response_type", "code")
+            .param("scope", "openid")
+            .param("redirect_uri", redirect_uri)
+            .param("state", "xyz")
+            .param_opt("max_age", &max_age.map(|m| m.to_string()))
+            .query();
+        let res = client
+            .get(uri)
+            .private_cookie(Cookie::new("session_id", session_id.to_string()))
+            .dispatch();
+        (
+            res.status(),
+            res.headers().get_one("Location").map(String::from),
+        )
+    }
+
+    #[test]
+    #[ignore = "needs the oidc_db database from Rocket.toml"]
+    fn get_authenticate_with_unregistered_redirect_uri_is_not_redirected() {
+        let (client, conn) = sso_client();
+        let (client_id, session_id) = sso_fixture(Utc::now().naive_utc(), true, &conn);
+        // single sign-on would issue a code without any interaction
+        let (status, location) = sso_request(
+            &client,
+            &client_id,
+            &session_id,
+            "https://evil.example.com/cb",
+            None,
+        );
+        assert_eq!(Status::BadRequest, status);
+        assert!(location.is_none());
+    }
+
+    #[test]
+    #[ignore = "needs the oidc_db database from Rocket.toml"]
+    fn get_authenticate_with_session_and_grant_issues_code() {
+        let (client, conn) = sso_client();
+        let (client_id, session_id) = sso_fixture(Utc::now().naive_utc(), true, &conn);
+        let (status, location) = sso_request(&client, &client_id, &session_id, CALLBACK, None);
+        assert_eq!(Status::Found, status);
+        let location = location.unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?code="));
+        assert!(location.contains("state=xyz"));
+    }
+
+    #[test]
+    #[ignore = "needs the oidc_db database from Rocket.toml"]
+    fn get_authenticate_with_session_past_max_age_asks_for_login() {
+        let (client, conn) = sso_client();
+        let auth_time = Utc::now().naive_utc() - Duration::minutes(10);
+        let (client_id, session_id) = sso_fixture(auth_time, true, &conn);
+        let (status, location) = sso_request(&client, &client_id, &session_id, CALLBACK, Some(60));
+        // the login page is rendered instead of redirecting anywhere
+        assert_eq!(Status::Ok, status);
+        assert!(location.is_none());
+    }
+
+    #[test]
+    #[ignore = "needs the oidc_db database from Rocket.toml"]
+    fn get_authenticate_with_session_without_grant_asks_for_consent() {
+        let (client, conn) = sso_client();
+        let (client_id, session_id) = sso_fixture(Utc::now().naive_utc(), false, &conn);
+        let (status, location) = sso_request(&client, &client_id, &session_id, CALLBACK, None);
+        assert_eq!(Status::SeeOther, status);
+        assert!(location
+            .unwrap()
+            .starts_with("/authorization?consent_challenge="));
+    }
+
+    /// Pushes the parameters of a code flow request for a new client,
+    /// returning the client_id and the request_uri
+    fn pushed_fixture(expires_at: NaiveDateTime, conn: &MysqlConnection) -> (String, String) {
+        let client_id = generate_challenge();
+        create_client(
+            crate::models::Client {
+                client_id: client_id.clone(),
+                ..crate::models::Client::for_test()
+            },
+            conn,
+        )
+        .unwrap();
+        let request_uri = format!("{}{}", REQUEST_URI_PREFIX, generate_challenge());
+        let params = format!(
+            r#"{{"client_id":"{}","response_type":"code","scope":"openid","redirect_uri":"{}"}}"#,
+            client_id, CALLBACK
+        );
+        repository::create_pushed_authorization_request(
+            PushedAuthorizationRequest {
+                request_uri: request_uri.clone(),
+                client_id: client_id.clone(),
+                params,
+                expires_at,
+            },
+            conn,
+        )
+        .unwrap();
+        (client_id, request_uri)
+    }
+
+    #[test]
+    #[ignore = "needs the oidc_db database from Rocket.toml"]
+    fn redeem_pushed_request_only_once() {
+        let (_client, conn) = sso_client();
+        let expires_at = Utc::now().naive_utc() + Duration::seconds(PAR_LIFETIME);
+        let (client_id, request_uri) = pushed_fixture(expires_at, &conn);
+        // another client can't redeem it, nor use it up
+        assert!(redeem_pushed_request(&request_uri, "other", &conn)
+            .unwrap()
+            .is_none());
+        let param = redeem_pushed_request(&request_uri, &client_id, &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!(Some(CALLBACK.to_string()), param.redirect_uri);
+        assert!(redeem_pushed_request(&request_uri, &client_id, &conn)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    #[ignore = "needs the oidc_db database from Rocket.toml"]
+    fn redeem_expired_pushed_request_fails() {
+        let (_client, conn) = sso_client();
+        let expires_at = Utc::now().naive_utc() - Duration::seconds(1);
+        let (client_id, request_uri) = pushed_fixture(expires_at, &conn);
+        assert!(redeem_pushed_request(&request_uri, &client_id, &conn)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    #[ignore = "needs the oidc_db database from Rocket.toml"]
+    fn get_authenticate_without_pushed_request_is_rejected_when_required() {
+        let (client, conn) = sso_client();
+        let client_id = generate_challenge();
+        create_client(
+            crate::models::Client {
+                client_id: client_id.clone(),
+                require_pushed_authorization_requests: true,
+                ..crate::models::Client::for_test()
+            },
+            &conn,
+        )
+        .unwrap();
+        let (status, location) = sso_request(&client, &client_id, "", CALLBACK, None);
+        assert_eq!(Status::Found, status);
+        let location = location.unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?error=invalid_request"));
+        assert!(!location.contains("code="));
+    }
+
+    #[test]
+    fn id_token_claims_auth_time_ok() {
+        let auth_time = Utc::now().naive_utc();
+        let expected = Some(auth_time.timestamp() as usize);
+        let mut client = crate::models::Client::for_test();
+        let claim = id_token_claims(&client, "user", "nonce", auth_time, Some(60), &None, &None);
+        assert_eq!(expected, claim.auth_time);
+        let claims =
+            ClaimsRequest::from_str(r#"{"id_token":{"auth_time":{"essential":true}}}"#).ok();
+        let claim = id_token_claims(&client, "user", "nonce", auth_time, None, &None, &claims);
+        assert_eq!(expected, claim.auth_time);
+        client.require_auth_time = true;
+        let claim = id_token_claims(&client, "user", "nonce", auth_time, None, &None, &None);
+        assert_eq!(expected, claim.auth_time);
+    }
+
+    #[test]
+    fn id_token_claims_auth_time_ng() {
+        let client = crate::models::Client::for_test();
+        let claim = id_token_claims(
+            &client,
+            "user",
+            "nonce",
+            Utc::now().naive_utc(),
+            None,
+            &None,
+            &None,
+        );
+        assert_eq!(None, claim.auth_time);
+        let jwt = sign_jwt(&claim).unwrap();
+        let claims = verify_jwt::<serde_json::Value>(&jwt).unwrap();
+        assert!(claims.get("auth_time").is_none());
+    }
 }
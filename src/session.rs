@@ -0,0 +1,111 @@
+use chrono::Utc;
+use diesel::MysqlConnection;
+use rocket::{
+    http::Status,
+    request::{self, FromRequest, Outcome},
+    Request,
+};
+
+use crate::{
+    config::SessionConfig,
+    error::CustomError,
+    message::login::add_session_cookies,
+    models::Session,
+    repository::{self, find_session},
+    server::DBPool,
+    utils::generate_challenge,
+};
+
+/// Loads the session unless it outlived its absolute or idle lifetime.
+/// Using the session restarts the idle timeout.
+pub fn find_active_session(
+    session_id: &str,
+    config: &SessionConfig,
+    conn: &MysqlConnection,
+) -> Result<Session, CustomError> {
+    let mut session = find_session(session_id, conn).or(Err(CustomError::SessionError))?;
+    if session.is_expired(config.session_idle_timeout) {
+        repository::delete_session_clients(&session.sid, conn)?;
+        repository::delete_session(session.session_id, conn)?;
+        return Err(CustomError::SessionError);
+    }
+    session.last_seen_at = Utc::now().naive_utc();
+    repository::touch_session(&session.session_id, session.last_seen_at, conn)?;
+    Ok(session)
+}
+
+/// The logged-in end-user's session, loaded from the private `session_id` cookie.
+/// Interactive routes take this guard instead of reading the cookie themselves;
+/// use `Option<AuthenticatedSession>` where logging in is optional.
+pub struct AuthenticatedSession {
+    pub session: Session,
+}
+
+impl AuthenticatedSession {
+    pub fn user_id(&self) -> &str {
+        &self.session.user_id
+    }
+
+    /// Checks the CSRF token submitted with an account form
+    pub fn check_csrf_token(&self, csrf_token: &str) -> Result<(), CustomError> {
+        if !self.session.has_csrf_token(csrf_token) {
+            return Err(CustomError::BadRequest);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedSession {
+    type Error = CustomError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let session_id = match request.cookies().get_private("session_id") {
+            Some(c) => c.value().to_string(),
+            None => return Outcome::Failure((Status::Unauthorized, CustomError::SessionError)),
+        };
+        let config = match request.rocket().state::<SessionConfig>() {
+            Some(config) => *config,
+            None => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    CustomError::ServerError(String::from("session configuration is missing")),
+                ))
+            }
+        };
+        let conn = match request.guard::<DBPool>().await {
+            Outcome::Success(conn) => conn,
+            _ => {
+                return Outcome::Failure((
+                    Status::ServiceUnavailable,
+                    CustomError::ServerError(String::from("database is unavailable")),
+                ))
+            }
+        };
+        let browser_state = request
+            .cookies()
+            .get("browser_state")
+            .map(|c| c.value().to_string())
+            .unwrap_or_else(generate_challenge);
+        match conn
+            .run(move |c| find_active_session(&session_id, &config, c))
+            .await
+        {
+            Ok(session) => {
+                // the cookies expire after the same idle timeout as the stored session
+                add_session_cookies(
+                    request.cookies(),
+                    session.session_id.clone(),
+                    browser_state,
+                    session.cookie_max_age(config.session_idle_timeout),
+                    config.session_cookie_secure,
+                );
+                Outcome::Success(AuthenticatedSession { session })
+            }
+            Err(CustomError::SessionError) => {
+                Outcome::Failure((Status::Unauthorized, CustomError::SessionError))
+            }
+            Err(e) => Outcome::Failure((Status::InternalServerError, e)),
+        }
+    }
+}
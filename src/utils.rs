@@ -1,13 +1,177 @@
-use std::time::{SystemTime, UNIX_EPOCH};
-
+use anyhow::anyhow;
 use crypto::{digest::Digest, sha2::Sha256};
+use josekit::{
+    jwk::Jwk,
+    jws::{JwsVerifier, ES256, ES384, ES512, PS256, PS384, PS512, RS256, RS384, RS512},
+};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+use url::Url;
+
+use crate::message::discovery::ISSUER;
 
+/// A random value for challenges, session ids and CSRF tokens, which must not be guessable
 pub fn generate_challenge() -> String {
-    let now_nano = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("something went wrong when calculate timestamp")
-        .as_nanos();
+    let mut challenge = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut challenge);
+    challenge.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Signs the claims with the provider's private key (RS256)
+pub fn sign_jwt<T: Serialize>(claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+    sign_jwt_with_type(claims, "JWT")
+}
+
+/// Signs the claims with an explicit `typ` header (e.g. `logout+jwt`)
+pub fn sign_jwt_with_type<T: Serialize>(
+    claims: &T,
+    typ: &str,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let mut jwt_header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+    jwt_header.typ = Some(typ.to_string());
+    jsonwebtoken::encode(
+        &jwt_header,
+        claims,
+        &jsonwebtoken::EncodingKey::from_rsa_pem(include_bytes!("private-key.pem"))?,
+    )
+}
+
+/// Verifies a JWT issued by this provider. Expired tokens are accepted
+/// because an ID token used as a hint is usually already expired.
+pub fn verify_jwt<T: DeserializeOwned>(token: &str) -> Result<T, jsonwebtoken::errors::Error> {
+    let validation = jsonwebtoken::Validation {
+        validate_exp: false,
+        iss: Some(ISSUER.to_string()),
+        ..jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256)
+    };
+    let data = jsonwebtoken::decode::<T>(
+        token,
+        &jsonwebtoken::DecodingKey::from_rsa_pem(include_bytes!("public-key.pem"))?,
+        &validation,
+    )?;
+    Ok(data.claims)
+}
+
+/// `alg` values JWTs signed by clients may use
+pub const JWS_ALGS: [&str; 9] = [
+    "RS256", "RS384", "RS512", "PS256", "PS384", "PS512", "ES256", "ES384", "ES512",
+];
+
+/// Builds the verifier for a JWS signed with `alg` by the holder of `jwk`
+pub fn jws_verifier(alg: &str, jwk: &Jwk) -> anyhow::Result<Box<dyn JwsVerifier>> {
+    let verifier: Box<dyn JwsVerifier> = match alg {
+        "RS256" => Box::new(RS256.verifier_from_jwk(jwk)?),
+        "RS384" => Box::new(RS384.verifier_from_jwk(jwk)?),
+        "RS512" => Box::new(RS512.verifier_from_jwk(jwk)?),
+        "PS256" => Box::new(PS256.verifier_from_jwk(jwk)?),
+        "PS384" => Box::new(PS384.verifier_from_jwk(jwk)?),
+        "PS512" => Box::new(PS512.verifier_from_jwk(jwk)?),
+        "ES256" => Box::new(ES256.verifier_from_jwk(jwk)?),
+        "ES384" => Box::new(ES384.verifier_from_jwk(jwk)?),
+        "ES512" => Box::new(ES512.verifier_from_jwk(jwk)?),
+        _ => return Err(anyhow!("unsupported signing alg {}", alg)),
+    };
+    Ok(verifier)
+}
+
+/// base64url of the SHA-256 hash
+pub fn sha256_base64url(value: &[u8]) -> String {
+    let mut hash_sha256 = Sha256::new();
+    hash_sha256.input(value);
+    let mut digest = [0u8; 32];
+    hash_sha256.result(&mut digest);
+    base64::encode_config(digest, base64::URL_SAFE_NO_PAD)
+}
+
+/// Computes at_hash / c_hash: base64url of the left-most half of the SHA-256 hash
+/// https://openid.net/specs/openid-connect-core-1_0.html#CodeIDToken
+pub fn left_half_hash(value: &str) -> String {
     let mut hash_sha256 = Sha256::new();
-    hash_sha256.input_str(&now_nano.to_string());
-    hash_sha256.result_str()
+    hash_sha256.input_str(value);
+    let mut digest = [0u8; 32];
+    hash_sha256.result(&mut digest);
+    base64::encode_config(&digest[..16], base64::URL_SAFE_NO_PAD)
+}
+
+/// Computes the session_state returned with authorization responses
+/// https://openid.net/specs/openid-connect-session-1_0.html#CreatingUpdatingSessions
+pub fn session_state(client_id: &str, origin: &str, browser_state: &str, salt: &str) -> String {
+    let mut hash_sha256 = Sha256::new();
+    hash_sha256.input_str(&format!(
+        "{} {} {} {}",
+        client_id, origin, browser_state, salt
+    ));
+    format!("{}.{}", hash_sha256.result_str(), salt)
+}
+
+/// Returns the origin (scheme, host and port) of an absolute URI
+pub fn origin(uri: &str) -> Option<String> {
+    let origin = Url::parse(uri).ok()?.origin();
+    if origin.is_tuple() {
+        Some(origin.ascii_serialization())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn generate_challenge_ok() {
+        let challenge = generate_challenge();
+        assert_eq!(64, challenge.len());
+        assert!(challenge.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(challenge, generate_challenge());
+    }
+
+    #[test]
+    fn verify_jwt_ok() {
+        let token = sign_jwt(&json!({ "iss": ISSUER, "sub": "user1", "exp": 0 })).unwrap();
+        let claims = verify_jwt::<Value>(&token).unwrap();
+        assert_eq!("user1", claims["sub"]);
+    }
+
+    #[test]
+    fn verify_jwt_ng() {
+        let token =
+            sign_jwt(&json!({ "iss": "https://other.example.com", "sub": "user1" })).unwrap();
+        assert!(verify_jwt::<Value>(&token).is_err());
+        let token = sign_jwt(&json!({ "sub": "user1" })).unwrap();
+        assert!(verify_jwt::<Value>(&token).is_err());
+    }
+
+    #[test]
+    fn left_half_hash_ok() {
+        let result = left_half_hash("Qcb0Orv1zh30vL1MPRsbm-diHiMwcLyZvn1arpZv-Jxf_11jnpEX3Tgfvk");
+        assert_eq!("LDktKdoQak3Pk0cnXxCltA", result);
+    }
+
+    #[test]
+    fn left_half_hash_access_token_ok() {
+        let result = left_half_hash("jHkWEdUXMU1BwAsC4vtUsZwnNg2BBrmL");
+        assert_eq!("v1NZZ33yjGFx41x0rFxPog", result);
+    }
+
+    #[test]
+    fn session_state_ok() {
+        let result = session_state("client1", "https://rp.example.com", "abc", "salt");
+        assert_eq!(
+            "d342d342067611292691278d1ed92c5978fea4457e86f9b87f2d00e3cbd83810.salt",
+            result
+        );
+    }
+
+    #[test]
+    fn origin_ok() {
+        let result = origin("https://rp.example.com:8443/cb?x=1#y");
+        assert_eq!(Some(String::from("https://rp.example.com:8443")), result);
+    }
+
+    #[test]
+    fn origin_ng() {
+        assert!(origin("/relative/path").is_none());
+    }
 }
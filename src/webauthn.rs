@@ -0,0 +1,538 @@
+//! WebAuthn (https://www.w3.org/TR/webauthn-2/) registration and assertion
+//! ceremonies for passkey login. Only the "none" attestation format is
+//! accepted: the provider doesn't need to know the authenticator's make.
+
+use crypto::{digest::Digest, sha2::Sha256};
+use rand::RngCore;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+use crate::{
+    cbor::{self, CborError, Value},
+    message::discovery::ISSUER,
+    utils::origin,
+};
+
+/// COSE algorithm identifiers the provider accepts, in order of preference
+pub const COSE_ALG_ES256: i128 = -7;
+pub const COSE_ALG_RS256: i128 = -257;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_BACKUP_ELIGIBLE: u8 = 0x08;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+#[derive(Debug, Error)]
+pub enum WebAuthnError {
+    #[error("malformed CBOR: {0}")]
+    Cbor(#[from] CborError),
+    #[error("malformed client data")]
+    ClientData,
+    #[error("unexpected ceremony type")]
+    Type,
+    #[error("challenge mismatch")]
+    Challenge,
+    #[error("origin mismatch")]
+    Origin,
+    #[error("malformed authenticator data")]
+    AuthenticatorData,
+    #[error("RP ID hash mismatch")]
+    RpId,
+    #[error("user wasn't present or verified")]
+    UserVerification,
+    #[error("unsupported attestation format")]
+    Attestation,
+    #[error("unsupported public key")]
+    PublicKey,
+    #[error("invalid signature")]
+    Signature,
+    #[error("signature counter didn't increase")]
+    SignCount,
+}
+
+/// Generates a random ceremony challenge, base64url encoded
+pub fn new_challenge() -> String {
+    let mut challenge = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut challenge);
+    encode(&challenge)
+}
+
+/// WebAuthn passes binary values around base64url encoded without padding
+pub fn encode(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+pub fn decode(value: &str) -> Option<Vec<u8>> {
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD).ok()
+}
+
+/// The relying party ID passkeys are scoped to: the issuer's host
+pub fn rp_id() -> String {
+    Url::parse(ISSUER)
+        .ok()
+        .and_then(|u| u.host_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// The origin the browser reports in the client data
+pub fn rp_origin() -> String {
+    origin(ISSUER).unwrap_or_default()
+}
+
+/// What the relying party expects the authenticator to have signed
+pub struct Ceremony<'a> {
+    /// base64url, as sent to the browser
+    pub challenge: &'a str,
+    pub origin: &'a str,
+    pub rp_id: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+}
+
+/// A credential created by a registration ceremony
+pub struct RegisteredCredential {
+    pub id: Vec<u8>,
+    /// COSE_Key encoded
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+    /// synced passkeys may be backed up; device-bound keys can't
+    pub backup_eligible: bool,
+}
+
+/// The verified outcome of an assertion ceremony
+pub struct Assertion {
+    pub sign_count: u32,
+    pub backup_eligible: bool,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    /// credential id and COSE public key, only present on registration
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl AuthenticatorData {
+    fn parse(data: &[u8]) -> Result<Self, WebAuthnError> {
+        if data.len() < 37 {
+            return Err(WebAuthnError::AuthenticatorData);
+        }
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+        let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            // aaguid (16 bytes), credential id length (2 bytes), credential id, public key
+            let rest = data
+                .get(37 + 16..)
+                .ok_or(WebAuthnError::AuthenticatorData)?;
+            if rest.len() < 2 {
+                return Err(WebAuthnError::AuthenticatorData);
+            }
+            let id_len = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            let id = rest
+                .get(2..2 + id_len)
+                .ok_or(WebAuthnError::AuthenticatorData)?;
+            let key = &rest[2 + id_len..];
+            let (_, key_len) = cbor::decode(key)?;
+            Some((id.to_vec(), key[..key_len].to_vec()))
+        } else {
+            None
+        };
+        Ok(AuthenticatorData {
+            rp_id_hash: data[..32].to_vec(),
+            flags,
+            sign_count,
+            attested_credential,
+        })
+    }
+
+    /// Checks the data was made for this relying party with the user present and verified
+    fn check(&self, rp_id: &str) -> Result<(), WebAuthnError> {
+        if self.rp_id_hash != sha256(rp_id.as_bytes()) {
+            return Err(WebAuthnError::RpId);
+        }
+        let required = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+        if self.flags & required != required {
+            return Err(WebAuthnError::UserVerification);
+        }
+        Ok(())
+    }
+
+    fn backup_eligible(&self) -> bool {
+        self.flags & FLAG_BACKUP_ELIGIBLE != 0
+    }
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    let mut hash_sha256 = Sha256::new();
+    hash_sha256.input(data);
+    let mut digest = vec![0u8; 32];
+    hash_sha256.result(&mut digest);
+    digest
+}
+
+fn check_client_data(
+    client_data_json: &[u8],
+    ceremony_type: &str,
+    ceremony: &Ceremony,
+) -> Result<(), WebAuthnError> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).or(Err(WebAuthnError::ClientData))?;
+    if client_data.ceremony_type != ceremony_type {
+        return Err(WebAuthnError::Type);
+    }
+    if client_data.challenge != ceremony.challenge {
+        return Err(WebAuthnError::Challenge);
+    }
+    if client_data.origin != ceremony.origin {
+        return Err(WebAuthnError::Origin);
+    }
+    Ok(())
+}
+
+/// Checks the signature with a COSE encoded ES256 or RS256 public key
+fn verify_signature(
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), WebAuthnError> {
+    let (key, _) = cbor::decode(public_key)?;
+    let param = |label: i128| {
+        key.get_int(label)
+            .and_then(|v| v.as_bytes())
+            .ok_or(WebAuthnError::PublicKey)
+    };
+    let kty = key.get_int(1).and_then(|v| v.as_integer());
+    let alg = key.get_int(3).and_then(|v| v.as_integer());
+    match (kty, alg) {
+        // EC2 on P-256
+        (Some(2), Some(COSE_ALG_ES256)) => {
+            if key.get_int(-1).and_then(|v| v.as_integer()) != Some(1) {
+                return Err(WebAuthnError::PublicKey);
+            }
+            let point = [&[0x04][..], param(-2)?, param(-3)?].concat();
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point)
+                .verify(message, signature)
+                .or(Err(WebAuthnError::Signature))
+        }
+        (Some(3), Some(COSE_ALG_RS256)) => RsaPublicKeyComponents {
+            n: param(-1)?,
+            e: param(-2)?,
+        }
+        .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature)
+        .or(Err(WebAuthnError::Signature)),
+        _ => Err(WebAuthnError::PublicKey),
+    }
+}
+
+/// Verifies the response to `navigator.credentials.create()`
+pub fn verify_registration(
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+    ceremony: &Ceremony,
+) -> Result<RegisteredCredential, WebAuthnError> {
+    check_client_data(client_data_json, "webauthn.create", ceremony)?;
+    let (attestation, _) = cbor::decode(attestation_object)?;
+    let fmt = attestation.get_text("fmt").and_then(|v| v.as_text());
+    let statement_is_empty =
+        matches!(attestation.get_text("attStmt"), Some(Value::Map(m)) if m.is_empty());
+    if fmt != Some("none") || !statement_is_empty {
+        return Err(WebAuthnError::Attestation);
+    }
+    let auth_data = attestation
+        .get_text("authData")
+        .and_then(|v| v.as_bytes())
+        .ok_or(WebAuthnError::AuthenticatorData)?;
+    let auth_data = AuthenticatorData::parse(auth_data)?;
+    auth_data.check(ceremony.rp_id)?;
+    let (id, public_key) = auth_data
+        .attested_credential
+        .clone()
+        .ok_or(WebAuthnError::AuthenticatorData)?;
+    // reject keys that couldn't be used to log in later
+    let (key, _) = cbor::decode(&public_key)?;
+    match key.get_int(3).and_then(|v| v.as_integer()) {
+        Some(COSE_ALG_ES256) | Some(COSE_ALG_RS256) => {}
+        _ => return Err(WebAuthnError::PublicKey),
+    }
+    Ok(RegisteredCredential {
+        id,
+        public_key,
+        sign_count: auth_data.sign_count,
+        backup_eligible: auth_data.backup_eligible(),
+    })
+}
+
+/// Verifies the response to `navigator.credentials.get()` against the stored
+/// credential. A signature counter that doesn't increase hints at a cloned
+/// authenticator; authenticators that don't count always report 0.
+pub fn verify_assertion(
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+    stored_sign_count: u32,
+    ceremony: &Ceremony,
+) -> Result<Assertion, WebAuthnError> {
+    check_client_data(client_data_json, "webauthn.get", ceremony)?;
+    let auth_data = AuthenticatorData::parse(authenticator_data)?;
+    auth_data.check(ceremony.rp_id)?;
+    let message = [authenticator_data, &sha256(client_data_json)].concat();
+    verify_signature(public_key, &message, signature)?;
+    if (auth_data.sign_count != 0 || stored_sign_count != 0)
+        && auth_data.sign_count <= stored_sign_count
+    {
+        return Err(WebAuthnError::SignCount);
+    }
+    Ok(Assertion {
+        sign_count: auth_data.sign_count,
+        backup_eligible: auth_data.backup_eligible(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    use super::*;
+
+    const CHALLENGE: &str = "Y2hhbGxlbmdl";
+    const CREDENTIAL_ID: &[u8] = b"credential-1";
+
+    fn ceremony() -> Ceremony<'static> {
+        Ceremony {
+            challenge: CHALLENGE,
+            origin: "http://example.com",
+            rp_id: "example.com",
+        }
+    }
+
+    fn cbor_header(major: u8, len: usize) -> Vec<u8> {
+        if len < 24 {
+            vec![(major << 5) | len as u8]
+        } else if len < 256 {
+            vec![(major << 5) | 24, len as u8]
+        } else {
+            vec![(major << 5) | 25, (len >> 8) as u8, len as u8]
+        }
+    }
+
+    fn cbor_bytes(data: &[u8]) -> Vec<u8> {
+        [cbor_header(2, data.len()), data.to_vec()].concat()
+    }
+
+    fn cbor_text(text: &str) -> Vec<u8> {
+        [cbor_header(3, text.len()), text.as_bytes().to_vec()].concat()
+    }
+
+    /// A software authenticator holding one P-256 key
+    struct Authenticator {
+        key_pair: EcdsaKeyPair,
+        flags: u8,
+    }
+
+    impl Authenticator {
+        fn new(flags: u8) -> Self {
+            let rng = SystemRandom::new();
+            let pkcs8 =
+                EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+            let key_pair =
+                EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap();
+            Authenticator { key_pair, flags }
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            let point = self.key_pair.public_key().as_ref();
+            [
+                // {1: 2, 3: -7, -1: 1, -2: x, -3: y}
+                vec![0xa5, 0x01, 0x02, 0x03, 0x26, 0x20, 0x01, 0x21],
+                cbor_bytes(&point[1..33]),
+                vec![0x22],
+                cbor_bytes(&point[33..]),
+            ]
+            .concat()
+        }
+
+        fn auth_data(&self, rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+            [
+                sha256(rp_id.as_bytes()),
+                vec![flags],
+                sign_count.to_be_bytes().to_vec(),
+            ]
+            .concat()
+        }
+
+        fn client_data(ceremony_type: &str, challenge: &str) -> Vec<u8> {
+            serde_json::json!({
+                "type": ceremony_type,
+                "challenge": challenge,
+                "origin": "http://example.com",
+                "crossOrigin": false,
+            })
+            .to_string()
+            .into_bytes()
+        }
+
+        fn attestation_object(&self, rp_id: &str) -> Vec<u8> {
+            let auth_data = [
+                self.auth_data(rp_id, self.flags | FLAG_ATTESTED_CREDENTIAL_DATA, 0),
+                vec![0; 16],
+                (CREDENTIAL_ID.len() as u16).to_be_bytes().to_vec(),
+                CREDENTIAL_ID.to_vec(),
+                self.cose_key(),
+            ]
+            .concat();
+            [
+                vec![0xa3],
+                cbor_text("fmt"),
+                cbor_text("none"),
+                cbor_text("attStmt"),
+                vec![0xa0],
+                cbor_text("authData"),
+                cbor_bytes(&auth_data),
+            ]
+            .concat()
+        }
+
+        /// Returns client data, authenticator data and signature
+        fn assert(&self, challenge: &str, sign_count: u32) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+            let client_data = Self::client_data("webauthn.get", challenge);
+            let auth_data = self.auth_data("example.com", self.flags, sign_count);
+            let message = [auth_data.clone(), sha256(&client_data)].concat();
+            let signature = self
+                .key_pair
+                .sign(&SystemRandom::new(), &message)
+                .unwrap()
+                .as_ref()
+                .to_vec();
+            (client_data, auth_data, signature)
+        }
+    }
+
+    const FLAGS: u8 = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+
+    #[test]
+    fn verify_registration_ok() {
+        let authenticator = Authenticator::new(FLAGS | FLAG_BACKUP_ELIGIBLE);
+        let credential = verify_registration(
+            &Authenticator::client_data("webauthn.create", CHALLENGE),
+            &authenticator.attestation_object("example.com"),
+            &ceremony(),
+        )
+        .unwrap();
+        assert_eq!(CREDENTIAL_ID, &credential.id[..]);
+        assert_eq!(authenticator.cose_key(), credential.public_key);
+        assert!(credential.backup_eligible);
+    }
+
+    #[test]
+    fn verify_registration_ng() {
+        let authenticator = Authenticator::new(FLAGS);
+        let attestation = authenticator.attestation_object("example.com");
+        let res = verify_registration(
+            &Authenticator::client_data("webauthn.create", "b3RoZXI"),
+            &attestation,
+            &ceremony(),
+        );
+        assert!(matches!(res, Err(WebAuthnError::Challenge)));
+        let res = verify_registration(
+            &Authenticator::client_data("webauthn.get", CHALLENGE),
+            &attestation,
+            &ceremony(),
+        );
+        assert!(matches!(res, Err(WebAuthnError::Type)));
+        let res = verify_registration(
+            &Authenticator::client_data("webauthn.create", CHALLENGE),
+            &authenticator.attestation_object("evil.example.com"),
+            &ceremony(),
+        );
+        assert!(matches!(res, Err(WebAuthnError::RpId)));
+        let unverified = Authenticator::new(FLAG_USER_PRESENT);
+        let res = verify_registration(
+            &Authenticator::client_data("webauthn.create", CHALLENGE),
+            &unverified.attestation_object("example.com"),
+            &ceremony(),
+        );
+        assert!(matches!(res, Err(WebAuthnError::UserVerification)));
+    }
+
+    #[test]
+    fn verify_assertion_ok() {
+        let authenticator = Authenticator::new(FLAGS);
+        let (client_data, auth_data, signature) = authenticator.assert(CHALLENGE, 5);
+        let assertion = verify_assertion(
+            &client_data,
+            &auth_data,
+            &signature,
+            &authenticator.cose_key(),
+            4,
+            &ceremony(),
+        )
+        .unwrap();
+        assert_eq!(5, assertion.sign_count);
+        assert!(!assertion.backup_eligible);
+        // authenticators that don't count always report 0
+        let (client_data, auth_data, signature) = authenticator.assert(CHALLENGE, 0);
+        assert!(verify_assertion(
+            &client_data,
+            &auth_data,
+            &signature,
+            &authenticator.cose_key(),
+            0,
+            &ceremony(),
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn verify_assertion_ng() {
+        let authenticator = Authenticator::new(FLAGS);
+        let other = Authenticator::new(FLAGS);
+        let (client_data, auth_data, signature) = authenticator.assert(CHALLENGE, 5);
+        let res = verify_assertion(
+            &client_data,
+            &auth_data,
+            &signature,
+            &other.cose_key(),
+            0,
+            &ceremony(),
+        );
+        assert!(matches!(res, Err(WebAuthnError::Signature)));
+        let res = verify_assertion(
+            &client_data,
+            &auth_data,
+            &signature,
+            &authenticator.cose_key(),
+            5,
+            &ceremony(),
+        );
+        assert!(matches!(res, Err(WebAuthnError::SignCount)));
+        let (client_data, auth_data, signature) = authenticator.assert("b3RoZXI", 6);
+        let res = verify_assertion(
+            &client_data,
+            &auth_data,
+            &signature,
+            &authenticator.cose_key(),
+            5,
+            &ceremony(),
+        );
+        assert!(matches!(res, Err(WebAuthnError::Challenge)));
+    }
+
+    #[test]
+    fn rp_id_ok() {
+        assert_eq!("example.com", rp_id());
+        assert_eq!("http://example.com", rp_origin());
+    }
+}